@@ -1,8 +1,11 @@
+#![allow(non_snake_case, clippy::empty_docs)]
 use approx::relative_ne;
 use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
 
 // 螺纹代号
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Tsify, Serialize, Deserialize)]
 pub enum ScrewType {
     M4,
     M5,
@@ -13,53 +16,165 @@ pub enum ScrewType {
     M14,
 }
 
+impl ScrewType {
+    // 公称直径 m
+    pub fn diameter(&self) -> f32 {
+        match self {
+            ScrewType::M4 => 0.004,
+            ScrewType::M5 => 0.005,
+            ScrewType::M6 => 0.006,
+            ScrewType::M8 => 0.008,
+            ScrewType::M10 => 0.010,
+            ScrewType::M12 => 0.012,
+            ScrewType::M14 => 0.014,
+        }
+    }
+}
+
+// 最小旋合长度 / 公称直径
+const MIN_ENGAGEMENT_RATIO: f32 = 1.0;
+
+// 同轴判断容差 m
+const COAXIAL_TOLERANCE: f32 = 0.0001;
+
+// 螺纹旋合检查结果
+#[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
+pub enum ScrewWarning {
+    Mismatch { inner: ScrewType, outer: ScrewType },
+    NotCoaxial,
+    InsufficientEngagement { engagement: f32, required: f32 }, // m
+    Protruding { protrusion: f32 },                            // m
+}
+
 // 内螺纹面
-struct InnerScrewFace {
+pub(crate) struct InnerScrewFace {
     screw_type: ScrewType,
     start: Vector3<f32>,
     end: Vector3<f32>,
+    through: bool, // 通孔 (螺母), 螺栓可以穿出
 }
 
 impl InnerScrewFace {
-    pub fn new(screw_type: ScrewType, start: Vector3<f32>, end: Vector3<f32>) -> Self {
-        let is_close = relative_ne!(start, end);
-        if !is_close {
-            panic!("start and end should not be the same point");
-        }
-        InnerScrewFace {
+    /// a tapped hole from its mouth `start` to its bottom `end`, None if the two are the same point
+    pub fn new(screw_type: ScrewType, start: Vector3<f32>, end: Vector3<f32>) -> Option<Self> {
+        relative_ne!(start, end).then_some(InnerScrewFace {
             screw_type,
             start,
             end,
+            through: false,
+        })
+    }
+
+    /// a threaded through hole, e.g. a nut, None if `start` and `end` are the same point
+    pub fn through_hole(
+        screw_type: ScrewType,
+        start: Vector3<f32>,
+        end: Vector3<f32>,
+    ) -> Option<Self> {
+        let mut face = InnerScrewFace::new(screw_type, start, end)?;
+        face.through = true;
+        Some(face)
+    }
+
+    /// whether the outer thread fits and actually overlaps this face
+    pub fn is_match(&self, outer_screw_face: &OuterScrewFace) -> bool {
+        self.screw_type == outer_screw_face.screw_type && self.engagement(outer_screw_face) > 0.
+    }
+
+    /// whether the outer thread lies on the axis of this face
+    pub fn is_coaxial(&self, outer_screw_face: &OuterScrewFace) -> bool {
+        self.project(outer_screw_face).is_some()
+    }
+
+    // 外螺纹端点在内螺纹轴线上的投影, start 为孔口, end 为螺纹孔底
+    fn project(&self, outer_screw_face: &OuterScrewFace) -> Option<(f32, f32)> {
+        let axis = self.end - self.start;
+        let depth = axis.norm();
+        if depth < f32::EPSILON {
+            return None;
+        }
+        let dir = axis / depth;
+        let mut t = [0.; 2];
+        for (t, p) in t
+            .iter_mut()
+            .zip([outer_screw_face.start, outer_screw_face.end])
+        {
+            let v = p - self.start;
+            *t = v.dot(&dir);
+            if (v - dir * *t).norm() > COAXIAL_TOLERANCE {
+                return None;
+            }
+        }
+        Some((t[0], t[1]))
+    }
+
+    /// axial overlap between this tapped face and a mating outer thread, 0 if not coaxial
+    pub fn engagement(&self, outer_screw_face: &OuterScrewFace) -> f32 {
+        match self.project(outer_screw_face) {
+            Some((a, b)) => {
+                let depth = (self.end - self.start).norm();
+                (b.max(a).min(depth) - a.min(b).max(0.)).max(0.)
+            }
+            None => 0.,
         }
     }
 
-    pub fn is_match(&self, outer_screw_face: OuterScrewFace) -> bool {
-        self.screw_type == outer_screw_face.screw_type
+    /// thread engagement warnings, outer face end is the bolt tip
+    pub fn check_engagement(&self, outer_screw_face: &OuterScrewFace) -> Vec<ScrewWarning> {
+        if self.screw_type != outer_screw_face.screw_type {
+            return vec![ScrewWarning::Mismatch {
+                inner: self.screw_type,
+                outer: outer_screw_face.screw_type,
+            }];
+        }
+        let Some((_, tip)) = self.project(outer_screw_face) else {
+            return vec![ScrewWarning::NotCoaxial];
+        };
+        let mut warnings = Vec::new();
+        let engagement = self.engagement(outer_screw_face);
+        // a nut is only as strong as its full height
+        let required = if self.through {
+            (self.end - self.start).norm()
+        } else {
+            self.screw_type.diameter() * MIN_ENGAGEMENT_RATIO
+        };
+        if engagement + COAXIAL_TOLERANCE < required {
+            warnings.push(ScrewWarning::InsufficientEngagement {
+                engagement,
+                required,
+            });
+        }
+        let protrusion = tip - (self.end - self.start).norm();
+        if !self.through && protrusion > COAXIAL_TOLERANCE {
+            warnings.push(ScrewWarning::Protruding { protrusion });
+        }
+        warnings
     }
 }
 
 // 外螺纹面
-struct OuterScrewFace {
+pub(crate) struct OuterScrewFace {
     screw_type: ScrewType,
     start: Vector3<f32>,
     end: Vector3<f32>,
 }
 
 impl OuterScrewFace {
-    pub fn new(screw_type: ScrewType, start: Vector3<f32>, end: Vector3<f32>) -> Self {
-        let is_close = relative_ne!(start, end);
-        if !is_close {
-            panic!("start and end should not be the same point");
-        }
-        OuterScrewFace {
+    /// a thread from `start` to the tip `end`, None if the two are the same point
+    pub fn new(screw_type: ScrewType, start: Vector3<f32>, end: Vector3<f32>) -> Option<Self> {
+        relative_ne!(start, end).then_some(OuterScrewFace {
             screw_type,
             start,
             end,
-        }
+        })
+    }
+
+    pub fn is_match(&self, inner_screw_face: &InnerScrewFace) -> bool {
+        inner_screw_face.is_match(self)
     }
 
-    pub fn is_match(&self, inner_screw_face: InnerScrewFace) -> bool {
-        self.screw_type == inner_screw_face.screw_type
+    pub fn check_engagement(&self, inner_screw_face: &InnerScrewFace) -> Vec<ScrewWarning> {
+        inner_screw_face.check_engagement(self)
     }
 }
 
@@ -130,4 +245,120 @@ mod test {
         let isf = InnerScrewFace::new(ScrewType::M8, start, end);
         let osf = OuterScrewFace::new(ScrewType::M8, start, end);
     }
+
+    #[wasm_bindgen_test]
+    fn engagement_test() {
+        // M8 tapped 12mm deep along +x
+        let isf = InnerScrewFace::new(
+            ScrewType::M8,
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.012, 0.0, 0.0),
+        )
+        .unwrap();
+        // bolt thread from 20mm outside to 10mm deep
+        let osf = OuterScrewFace::new(
+            ScrewType::M8,
+            Vector3::new(-0.02, 0.0, 0.0),
+            Vector3::new(0.01, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!((isf.engagement(&osf) - 0.01).abs() < 1e-6);
+        assert!(isf.check_engagement(&osf).is_empty());
+        assert!(osf.check_engagement(&isf).is_empty());
+        assert!(isf.is_match(&osf));
+        assert!(osf.is_match(&isf));
+
+        // the tip may pass through a nut
+        let nut = InnerScrewFace::through_hole(
+            ScrewType::M8,
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.006, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!(nut.check_engagement(&osf).is_empty());
+        let short = OuterScrewFace::new(
+            ScrewType::M8,
+            Vector3::new(-0.02, 0.0, 0.0),
+            Vector3::new(0.004, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!(matches!(
+            nut.check_engagement(&short).as_slice(),
+            [ScrewWarning::InsufficientEngagement { .. }]
+        ));
+
+        // a zero length face is no face
+        let p = Vector3::new(0.01, 0.0, 0.0);
+        assert!(InnerScrewFace::new(ScrewType::M8, p, p).is_none());
+        assert!(OuterScrewFace::new(ScrewType::M8, p, p).is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn engagement_warning_test() {
+        let isf = InnerScrewFace::new(
+            ScrewType::M8,
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.012, 0.0, 0.0),
+        )
+        .unwrap();
+
+        // only 5mm engaged
+        let short = OuterScrewFace::new(
+            ScrewType::M8,
+            Vector3::new(-0.02, 0.0, 0.0),
+            Vector3::new(0.005, 0.0, 0.0),
+        )
+        .unwrap();
+        match isf.check_engagement(&short).as_slice() {
+            [ScrewWarning::InsufficientEngagement {
+                engagement,
+                required,
+            }] => {
+                assert!((engagement - 0.005).abs() < 1e-6);
+                assert!((required - 0.008).abs() < 1e-6);
+            }
+            w => panic!("unexpected warnings {:?}", w),
+        }
+
+        // tip 3mm past the tapped depth
+        let long = OuterScrewFace::new(
+            ScrewType::M8,
+            Vector3::new(-0.02, 0.0, 0.0),
+            Vector3::new(0.015, 0.0, 0.0),
+        )
+        .unwrap();
+        match isf.check_engagement(&long).as_slice() {
+            [ScrewWarning::Protruding { protrusion }] => {
+                assert!((protrusion - 0.003).abs() < 1e-6)
+            }
+            w => panic!("unexpected warnings {:?}", w),
+        }
+
+        let offset = OuterScrewFace::new(
+            ScrewType::M8,
+            Vector3::new(-0.02, 0.001, 0.0),
+            Vector3::new(0.01, 0.001, 0.0),
+        )
+        .unwrap();
+        assert_eq!(isf.engagement(&offset), 0.);
+        assert!(!isf.is_match(&offset));
+        assert_eq!(
+            isf.check_engagement(&offset),
+            vec![ScrewWarning::NotCoaxial]
+        );
+
+        let m6 = OuterScrewFace::new(
+            ScrewType::M6,
+            Vector3::new(-0.02, 0.0, 0.0),
+            Vector3::new(0.01, 0.0, 0.0),
+        )
+        .unwrap();
+        assert_eq!(
+            isf.check_engagement(&m6),
+            vec![ScrewWarning::Mismatch {
+                inner: ScrewType::M8,
+                outer: ScrewType::M6,
+            }]
+        );
+    }
 }
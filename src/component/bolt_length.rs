@@ -0,0 +1,222 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use super::{
+    compatibility::SlotSpec,
+    extrude_connector::{BoltData, ExtrudeConnectorData, ExtrudeNutData, ExtrudeNutType, NutData},
+    ComponentData, ComponentLib,
+};
+
+// 螺栓末端与槽底之间的最小间隙 0.01mm
+const SLOT_BOTTOM_CLEARANCE: u32 = 50;
+
+// 弹簧螺母的弹簧在槽底占用的高度 0.01mm
+const NUT_SPRING_HEIGHT: u32 = 150;
+
+// 紧固叠层: 螺栓依次穿过角码, 连接板, 拧入型材槽内的螺母
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ClampStack {
+    pub profile: String,        // 型材
+    pub nut: String,            // 槽内螺母
+    pub bracket_thickness: u32, // 0.01mm
+    pub plate_thickness: u32,   // 0.01mm
+}
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct BoltRecommendation {
+    pub min_length: u32,          // 0.01mm
+    pub max_length: u32,          // 0.01mm
+    pub bolt: Option<String>,     // 为 None 时目录中没有可用螺栓
+    pub bolt_length: Option<u32>, // 0.01mm
+}
+
+impl BoltRecommendation {
+    pub fn is_valid(&self) -> bool {
+        self.bolt.is_some()
+    }
+}
+
+/// bolt length range of a clamp stack, empty (min > max) if no length can work
+pub(crate) fn bolt_length_range(
+    clamp_thickness: u32,
+    nut: &ExtrudeNutData,
+    slot: &SlotSpec,
+) -> (u32, u32) {
+    // the thread should pass through the whole nut
+    let min = clamp_thickness + nut.series.height();
+    // but must not bottom out in the slot
+    let spring = match nut.extrude_nut_type {
+        ExtrudeNutType::SpringPlate | ExtrudeNutType::SpringBall => NUT_SPRING_HEIGHT,
+        ExtrudeNutType::Slide | ExtrudeNutType::T => 0,
+    };
+    let max = (clamp_thickness + slot.depth).saturating_sub(SLOT_BOTTOM_CLEARANCE + spring);
+    (min, max)
+}
+
+impl ComponentData {
+    /// length of a bolt 0.01mm, None if the component is not a bolt
+    pub(crate) fn bolt_length(&self) -> Option<u32> {
+        match self {
+            ComponentData::ExtrudeConnector(ExtrudeConnectorData::Bolt(BoltData::Extrude(b))) => {
+                Some(b.bolt_length)
+            }
+            ComponentData::ExtrudeConnector(ExtrudeConnectorData::Bolt(BoltData::Normal(b))) => {
+                Some(b.bolt_length as u32 * 100)
+            }
+            _ => None,
+        }
+    }
+
+    /// whether the component is a nut held in a profile slot
+    pub(crate) fn is_slot_nut(&self) -> bool {
+        matches!(
+            self,
+            ComponentData::ExtrudeConnector(ExtrudeConnectorData::Nut(NutData::Extrude(_)))
+        )
+    }
+}
+
+impl ComponentLib {
+    /// pick the catalog bolt nearest to the middle of the valid length range
+    pub(crate) fn recommend_bolt(&self, stack: &ClampStack) -> Result<BoltRecommendation, String> {
+        self.recommend_bolt_for(
+            &stack.profile,
+            &stack.nut,
+            stack.bracket_thickness + stack.plate_thickness,
+        )
+    }
+
+    /// the recommendation for a bolt clamping `clamp_thickness` 0.01mm onto the nut `nut`
+    /// in a slot of the profile `profile`
+    pub(crate) fn recommend_bolt_for(
+        &self,
+        profile: &str,
+        nut: &str,
+        clamp_thickness: u32,
+    ) -> Result<BoltRecommendation, String> {
+        let slot = match self.components.get(profile).map(|c| &c.data) {
+            Some(ComponentData::Extrude(e)) => e.slot_spec(),
+            _ => return Err(format!("invalid profile: {}", profile)),
+        };
+        let nut = match self.components.get(nut).map(|c| &c.data) {
+            Some(ComponentData::ExtrudeConnector(
+                c @ ExtrudeConnectorData::Nut(NutData::Extrude(n)),
+            )) => {
+                c.slot_requirement()
+                    .unwrap_or_default()
+                    .check(&slot)
+                    .map_err(|e| e.to_string())?;
+                n
+            }
+            _ => return Err(format!("invalid nut: {}", nut)),
+        };
+
+        let (min, max) = bolt_length_range(clamp_thickness, nut, &slot);
+
+        let middle = (min + max) / 2;
+        let mut candidates: Vec<(&String, u32)> = self
+            .components
+            .values()
+            .filter_map(|c| match &c.data {
+                ComponentData::ExtrudeConnector(ExtrudeConnectorData::Bolt(BoltData::Extrude(
+                    b,
+                ))) if b.hole == nut.hole => Some((&c.label, b.bolt_length)),
+                ComponentData::ExtrudeConnector(ExtrudeConnectorData::Bolt(BoltData::Normal(
+                    b,
+                ))) if b.slot_width as u32 * 100 == slot.width => {
+                    Some((&c.label, b.bolt_length as u32 * 100))
+                }
+                _ => None,
+            })
+            .filter(|(_, length)| (min..=max).contains(length))
+            .collect();
+        candidates.sort_by_key(|(label, length)| (length.abs_diff(middle), (*label).clone()));
+
+        let nearest = candidates.first();
+        Ok(BoltRecommendation {
+            min_length: min,
+            max_length: max,
+            bolt: nearest.map(|(label, _)| (*label).clone()),
+            bolt_length: nearest.map(|(_, length)| *length),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::component::{
+        extrude_connector::{ExtrudeNutSeries, Hole},
+        test_util::{bolt_lib as lib, connector},
+    };
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn recommend_bolt_test() {
+        let lib = lib();
+        let recommendation = lib
+            .recommend_bolt(&ClampStack {
+                profile: "LCF8-4040".into(),
+                nut: "Nut-SW8-M8".into(),
+                bracket_thickness: 500,
+                plate_thickness: 0,
+            })
+            .unwrap();
+        // 5mm bracket + 6mm nut .. 5mm bracket + 12.3mm slot - 0.5mm clearance
+        assert_eq!(recommendation.min_length, 1100);
+        assert_eq!(recommendation.max_length, 1680);
+        assert_eq!(recommendation.bolt, Some("Bolt-M8-12".to_string()));
+        assert_eq!(recommendation.bolt_length, Some(1200));
+    }
+
+    #[wasm_bindgen_test]
+    fn no_valid_bolt_test() {
+        let lib = lib();
+        let recommendation = lib
+            .recommend_bolt(&ClampStack {
+                profile: "LCF8-4040".into(),
+                nut: "Nut-SW8-M8".into(),
+                bracket_thickness: 1000,
+                plate_thickness: 2000,
+            })
+            .unwrap();
+        assert!(!recommendation.is_valid());
+        assert_eq!(recommendation.min_length, 3600);
+        assert_eq!(recommendation.max_length, 4180);
+
+        // the spring under the nut takes up slot depth
+        let mut lib = lib;
+        lib.add_component(connector(
+            "SpringNut-SW8-M8",
+            ExtrudeConnectorData::Nut(NutData::Extrude(ExtrudeNutData {
+                extrude_nut_type: ExtrudeNutType::SpringPlate,
+                series: ExtrudeNutSeries::SW8mm,
+                hole: Hole::M8,
+            })),
+        ));
+        let recommendation = lib
+            .recommend_bolt(&ClampStack {
+                profile: "LCF8-4040".into(),
+                nut: "SpringNut-SW8-M8".into(),
+                bracket_thickness: 0,
+                plate_thickness: 0,
+            })
+            .unwrap();
+        assert_eq!(recommendation.min_length, 600);
+        assert_eq!(recommendation.max_length, 1030);
+        assert!(!recommendation.is_valid());
+
+        assert!(lib
+            .recommend_bolt(&ClampStack {
+                profile: "WoodenPanel-test".into(),
+                nut: "Nut-SW8-M8".into(),
+                bracket_thickness: 500,
+                plate_thickness: 0,
+            })
+            .is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+
+use super::{
+    extrude::{ExtrudeSeries, S40ExtrudeSlotDepth},
+    extrude_connector::{
+        BoltData, BracketSeries, ElasticFastenerSeries, ExtrudeConnectorData, ExtrudeNutSeries,
+        NutData, SlotBracketSeries,
+    },
+    Component, ComponentData, ComponentLib, ExtrudeData,
+};
+
+// 型材系列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SlotSeries {
+    S20,
+    S30,
+    S40,
+}
+
+// 型材槽口规格
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SlotSpec {
+    pub(crate) series: SlotSeries,
+    pub(crate) width: u32, // 槽宽 0.01mm
+    pub(crate) depth: u32, // 槽深 0.01mm
+}
+
+impl ExtrudeSeries {
+    pub(crate) fn slot_spec(&self) -> SlotSpec {
+        match self {
+            ExtrudeSeries::S20() => SlotSpec {
+                series: SlotSeries::S20,
+                width: 600,
+                depth: 600,
+            },
+            ExtrudeSeries::S30() => SlotSpec {
+                series: SlotSeries::S30,
+                width: 800,
+                depth: 900,
+            },
+            ExtrudeSeries::S40(depth) => SlotSpec {
+                series: SlotSeries::S40,
+                width: 800,
+                depth: depth.depth(),
+            },
+        }
+    }
+}
+
+impl S40ExtrudeSlotDepth {
+    // 0.01mm
+    pub(crate) fn depth(&self) -> u32 {
+        match self {
+            S40ExtrudeSlotDepth::SlotDepth14_7mm => 1470,
+            S40ExtrudeSlotDepth::SlotDepth12_3mm => 1230,
+        }
+    }
+}
+
+impl ExtrudeData {
+    pub(crate) fn slot_spec(&self) -> SlotSpec {
+        self.standard.series.slot_spec()
+    }
+}
+
+// 连接件对槽口的要求, None 表示不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct SlotRequirement {
+    pub(crate) series: Option<SlotSeries>,
+    pub(crate) width: Option<u32>,     // 0.01mm
+    pub(crate) min_depth: Option<u32>, // 0.01mm
+}
+
+impl BracketSeries {
+    fn slot_series(&self) -> SlotSeries {
+        match self {
+            BracketSeries::S2020 => SlotSeries::S20,
+            BracketSeries::S3030 | BracketSeries::S3060 | BracketSeries::S6060 => SlotSeries::S30,
+            BracketSeries::S4040 | BracketSeries::S4080 | BracketSeries::S8080 => SlotSeries::S40,
+        }
+    }
+}
+
+impl ExtrudeNutSeries {
+    // 适配槽宽 0.01mm
+    fn slot_width(&self) -> u32 {
+        match self {
+            ExtrudeNutSeries::SW4mm => 400,
+            ExtrudeNutSeries::SW8mm => 800,
+            ExtrudeNutSeries::SW10mm => 1000,
+        }
+    }
+
+    // 螺母本体高度 0.01mm, 槽深不能小于该值
+    pub(crate) fn height(&self) -> u32 {
+        match self {
+            ExtrudeNutSeries::SW4mm => 300,
+            ExtrudeNutSeries::SW8mm => 600,
+            ExtrudeNutSeries::SW10mm => 800,
+        }
+    }
+}
+
+impl ExtrudeConnectorData {
+    /// slot requirement of a connector, None if it is not mounted in a slot
+    pub(crate) fn slot_requirement(&self) -> Option<SlotRequirement> {
+        match self {
+            ExtrudeConnectorData::Bracket(b) => Some(SlotRequirement {
+                series: Some(b.series.slot_series()),
+                ..Default::default()
+            }),
+            ExtrudeConnectorData::SlotBracket(b) => Some(SlotRequirement {
+                series: Some(match b.series {
+                    SlotBracketSeries::S20 => SlotSeries::S20,
+                    SlotBracketSeries::S30 => SlotSeries::S30,
+                    SlotBracketSeries::S40 => SlotSeries::S40,
+                }),
+                ..Default::default()
+            }),
+            ExtrudeConnectorData::ElasticFastener(f) => Some(SlotRequirement {
+                series: Some(match f.series {
+                    ElasticFastenerSeries::S30 => SlotSeries::S30,
+                    ElasticFastenerSeries::S40 => SlotSeries::S40,
+                }),
+                ..Default::default()
+            }),
+            ExtrudeConnectorData::Nut(NutData::Extrude(n)) => Some(SlotRequirement {
+                series: None,
+                width: Some(n.series.slot_width()),
+                min_depth: Some(n.series.height()),
+            }),
+            ExtrudeConnectorData::Bolt(BoltData::Normal(b)) => Some(SlotRequirement {
+                series: None,
+                width: Some(b.slot_width as u32 * 100),
+                min_depth: None,
+            }),
+            ExtrudeConnectorData::Bolt(BoltData::Extrude(_)) => Some(SlotRequirement::default()),
+            ExtrudeConnectorData::Nut(NutData::Normal(_))
+            | ExtrudeConnectorData::ConnectorPlate(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Incompatible {
+    NotExtrude,
+    NotSlotConnector,
+    Series {
+        slot: SlotSeries,
+        connector: SlotSeries,
+    },
+    SlotWidth {
+        slot: u32,
+        connector: u32,
+    },
+    SlotDepth {
+        slot: u32,
+        min_depth: u32,
+    },
+}
+
+impl fmt::Display for Incompatible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Incompatible::NotExtrude => write!(f, "invalid component type: Not Extrude"),
+            Incompatible::NotSlotConnector => {
+                write!(f, "invalid component type: Not Slot Connector")
+            }
+            Incompatible::Series { slot, connector } => {
+                write!(
+                    f,
+                    "series mismatch: slot {:?}, connector {:?}",
+                    slot, connector
+                )
+            }
+            Incompatible::SlotWidth { slot, connector } => write!(
+                f,
+                "slot width mismatch: slot {:.2}mm, connector {:.2}mm",
+                *slot as f32 / 100.,
+                *connector as f32 / 100.
+            ),
+            Incompatible::SlotDepth { slot, min_depth } => write!(
+                f,
+                "slot too shallow: slot {:.2}mm, connector needs {:.2}mm",
+                *slot as f32 / 100.,
+                *min_depth as f32 / 100.
+            ),
+        }
+    }
+}
+
+impl SlotRequirement {
+    pub(crate) fn check(&self, slot: &SlotSpec) -> Result<(), Incompatible> {
+        if let Some(series) = self.series {
+            if series != slot.series {
+                return Err(Incompatible::Series {
+                    slot: slot.series,
+                    connector: series,
+                });
+            }
+        }
+        if let Some(width) = self.width {
+            if width != slot.width {
+                return Err(Incompatible::SlotWidth {
+                    slot: slot.width,
+                    connector: width,
+                });
+            }
+        }
+        if let Some(min_depth) = self.min_depth {
+            if slot.depth < min_depth {
+                return Err(Incompatible::SlotDepth {
+                    slot: slot.depth,
+                    min_depth,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// check whether `connector` can be placed in a slot of `profile`
+pub(crate) fn check_slot_compatible(
+    profile: &Component,
+    connector: &Component,
+) -> Result<(), Incompatible> {
+    let slot = match &profile.data {
+        ComponentData::Extrude(e) => e.slot_spec(),
+        _ => return Err(Incompatible::NotExtrude),
+    };
+    let requirement = match &connector.data {
+        ComponentData::ExtrudeConnector(c) => c.slot_requirement(),
+        _ => None,
+    }
+    .ok_or(Incompatible::NotSlotConnector)?;
+    requirement.check(&slot)
+}
+
+impl ComponentLib {
+    /// connectors in the lib which fit the slot of the profile `label`
+    pub(crate) fn compatible_connectors(&self, label: &str) -> Vec<&Component> {
+        let Some(profile) = self.components.get(label) else {
+            return Vec::new();
+        };
+        let mut connectors: Vec<&Component> = self
+            .components
+            .values()
+            .filter(|c| check_slot_compatible(profile, c).is_ok())
+            .collect();
+        connectors.sort_by(|a, b| a.label.cmp(&b.label));
+        connectors
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::component::test_util::slot_connector_lib as lib;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn compatible_connectors_test() {
+        let lib = lib();
+        let labels: Vec<&str> = lib
+            .compatible_connectors("LCF8-4040")
+            .iter()
+            .map(|c| c.label.as_str())
+            .collect();
+        assert_eq!(
+            labels,
+            vec!["Bracket-4040", "ElasticFastener-40", "Nut-SW8"]
+        );
+        assert!(lib.compatible_connectors("WoodenPanel-test").is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn incompatible_connector_test() {
+        let lib = lib();
+        let profile = lib.components.get("LCF8-4040").unwrap();
+        assert_eq!(
+            check_slot_compatible(profile, lib.components.get("SlotBracket-20").unwrap()),
+            Err(Incompatible::Series {
+                slot: SlotSeries::S40,
+                connector: SlotSeries::S20,
+            })
+        );
+        assert_eq!(
+            check_slot_compatible(profile, lib.components.get("Nut-SW10").unwrap()),
+            Err(Incompatible::SlotWidth {
+                slot: 800,
+                connector: 1000,
+            })
+        );
+        assert_eq!(
+            check_slot_compatible(profile, lib.components.get("Accessory-test").unwrap()),
+            Err(Incompatible::NotSlotConnector)
+        );
+    }
+}
@@ -10,6 +10,21 @@ pub struct ExtrudeData {
     pub(crate) post_process: ExtrudePostProcess,
 }
 
+impl ExtrudeData {
+    // 截面尺寸 (y, z) m, 长方形竖向放置, 长边沿 y
+    pub(crate) fn section_size(&self) -> (f32, f32) {
+        let unit = match self.standard.series {
+            ExtrudeSeries::S20() => 0.02,
+            ExtrudeSeries::S30() => 0.03,
+            ExtrudeSeries::S40(_) => 0.04,
+        };
+        match self.shape.shape {
+            ExtrudeShapeEnum::Square(_) => (unit, unit),
+            ExtrudeShapeEnum::Rect(n, _) => (unit * n as f32, unit),
+        }
+    }
+}
+
 // 铝型材标准
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub(crate) struct ExtrudeStandard {
@@ -105,6 +120,23 @@ pub(crate) struct ExtrudeLength {
     pub(crate) step: u32, // 0.01mm
 }
 
+impl ExtrudeLength {
+    // 取最近的步长倍数, 并限制在可加工范围内
+    pub(crate) fn snap(&self, length: u32) -> u32 {
+        let step = self.step.max(1);
+        // 先限制到最大长度, 避免 JS 传入的大数溢出
+        let length = length.min(self.max).saturating_add(step / 2) / step * step;
+        length.clamp(self.min, self.max)
+    }
+
+    // 取不超过 length 的步长倍数, 超出可加工范围时为 None
+    pub(crate) fn snap_down(&self, length: u32) -> Option<u32> {
+        let step = self.step.max(1);
+        let length = length / step * step;
+        (self.min..=self.max).contains(&length).then_some(length)
+    }
+}
+
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub(crate) enum Drill {
     M6_15mm,
@@ -15,10 +15,10 @@ pub enum ExtrudeConnectorData {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct BracketData {
-    series: BracketSeries,
-    load: BracketLoad,
-    surface: BracketSurface,
-    manufacture_method: BracketManufactureMethod,
+    pub(crate) series: BracketSeries,
+    pub(crate) load: BracketLoad,
+    pub(crate) surface: BracketSurface,
+    pub(crate) manufacture_method: BracketManufactureMethod,
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -66,8 +66,8 @@ pub enum BracketAngle {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct SlotBracketData {
-    series: SlotBracketSeries,
-    material: SlotBracketMaterial,
+    pub(crate) series: SlotBracketSeries,
+    pub(crate) material: SlotBracketMaterial,
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -91,7 +91,7 @@ pub enum ConnectorPlateData {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct OuterConnectorPlateData {
-    plate_type: OuterConnectorPlateType,
+    pub(crate) plate_type: OuterConnectorPlateType,
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -102,8 +102,8 @@ pub enum OuterConnectorPlateType {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct InnerConnectorPlateData {
-    plate_type: InnerConnectorPlateType,
-    hole: Hole,
+    pub(crate) plate_type: InnerConnectorPlateType,
+    pub(crate) hole: Hole,
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -120,8 +120,8 @@ pub enum NutData {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct NormalNutData {
-    nut_type: NormalNutType,
-    hole: Hole,
+    pub(crate) nut_type: NormalNutType,
+    pub(crate) hole: Hole,
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -131,9 +131,9 @@ pub enum NormalNutType {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct ExtrudeNutData {
-    extrude_nut_type: ExtrudeNutType,
-    series: ExtrudeNutSeries,
-    hole: Hole,
+    pub(crate) extrude_nut_type: ExtrudeNutType,
+    pub(crate) series: ExtrudeNutSeries,
+    pub(crate) hole: Hole,
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -151,7 +151,7 @@ pub enum ExtrudeNutType {
     SpringBall,
 }
 
-#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Tsify, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Hole {
     M4,
     M5,
@@ -170,9 +170,9 @@ pub enum BoltData {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct NormalBoltData {
-    bolt_type: NormalBoltType,
-    slot_width: u8,
-    bolt_length: u16,
+    pub(crate) bolt_type: NormalBoltType,
+    pub(crate) slot_width: u8,   // mm
+    pub(crate) bolt_length: u16, // mm
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -184,9 +184,9 @@ pub enum NormalBoltType {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct ExtrudeBoltData {
-    extrude_bolt_type: ExtrudeBoltType,
-    hole: Hole,
-    bolt_length: u32, // 0.01mm
+    pub(crate) extrude_bolt_type: ExtrudeBoltType,
+    pub(crate) hole: Hole,
+    pub(crate) bolt_length: u32, // 0.01mm
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -196,7 +196,7 @@ pub enum ExtrudeBoltType {
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct ElasticFastenerData {
-    series: ElasticFastenerSeries,
+    pub(crate) series: ElasticFastenerSeries,
 }
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -1,10 +1,17 @@
+mod bolt_length;
+mod compatibility;
 mod end_cap;
 mod extrude;
 mod extrude_connector;
 mod floor;
 mod panel;
+#[cfg(test)]
+pub(crate) mod test_util;
+mod thread;
 use std::collections::HashMap;
 
+pub use bolt_length::{BoltRecommendation, ClampStack};
+pub(crate) use compatibility::{check_slot_compatible, Incompatible};
 pub use end_cap::EndCapData;
 pub use extrude::ExtrudeData;
 pub use extrude_connector::ExtrudeConnectorData;
@@ -248,7 +255,13 @@ pub fn misumi_40160_extrude(label: String, name: String, vendor: Vender) -> Comp
     }
 }
 
+#[wasm_bindgen]
+pub fn is_connector_compatible(profile: &Component, connector: &Component) -> bool {
+    check_slot_compatible(profile, connector).is_ok()
+}
+
 // #[wasm_bindgen]
+#[derive(Debug)]
 pub struct ComponentLib {
     pub(crate) components: HashMap<String, Component>,
 }
@@ -0,0 +1,87 @@
+use super::{
+    extrude_connector::{
+        BoltData, BracketData, BracketLoad, BracketManufactureMethod, BracketSeries,
+        BracketSurface, ElasticFastenerData, ElasticFastenerSeries, ExtrudeBoltData,
+        ExtrudeBoltType, ExtrudeConnectorData, ExtrudeNutData, ExtrudeNutSeries, ExtrudeNutType,
+        Hole, NutData, SlotBracketData, SlotBracketMaterial, SlotBracketSeries,
+    },
+    Component, ComponentData, ComponentLib, Vender,
+};
+
+/// an extrude connector component labelled `label`
+pub(crate) fn connector(label: &str, data: ExtrudeConnectorData) -> Component {
+    Component::new(
+        label.into(),
+        label.into(),
+        ComponentData::ExtrudeConnector(data),
+        Vender::new("test".into()),
+    )
+}
+
+/// the default lib with connectors of every slot kind
+pub(crate) fn slot_connector_lib() -> ComponentLib {
+    let mut lib = ComponentLib::default();
+    lib.add_component(connector(
+        "Bracket-4040",
+        ExtrudeConnectorData::Bracket(BracketData {
+            series: BracketSeries::S4040,
+            load: BracketLoad::Standard,
+            surface: BracketSurface::White,
+            manufacture_method: BracketManufactureMethod::Casting,
+        }),
+    ));
+    lib.add_component(connector(
+        "SlotBracket-20",
+        ExtrudeConnectorData::SlotBracket(SlotBracketData {
+            series: SlotBracketSeries::S20,
+            material: SlotBracketMaterial::Steel,
+        }),
+    ));
+    lib.add_component(connector(
+        "ElasticFastener-40",
+        ExtrudeConnectorData::ElasticFastener(ElasticFastenerData {
+            series: ElasticFastenerSeries::S40,
+        }),
+    ));
+    lib.add_component(connector(
+        "Nut-SW8",
+        ExtrudeConnectorData::Nut(NutData::Extrude(ExtrudeNutData {
+            extrude_nut_type: ExtrudeNutType::T,
+            series: ExtrudeNutSeries::SW8mm,
+            hole: Hole::M8,
+        })),
+    ));
+    lib.add_component(connector(
+        "Nut-SW10",
+        ExtrudeConnectorData::Nut(NutData::Extrude(ExtrudeNutData {
+            extrude_nut_type: ExtrudeNutType::T,
+            series: ExtrudeNutSeries::SW10mm,
+            hole: Hole::M8,
+        })),
+    ));
+    lib
+}
+
+/// the default lib with an M8 slot nut and M8 bolts of 12..30mm
+pub(crate) fn bolt_lib() -> ComponentLib {
+    let mut lib = ComponentLib::default();
+    lib.add_component(connector(
+        "Nut-SW8-M8",
+        ExtrudeConnectorData::Nut(NutData::Extrude(ExtrudeNutData {
+            extrude_nut_type: ExtrudeNutType::T,
+            series: ExtrudeNutSeries::SW8mm,
+            hole: Hole::M8,
+        })),
+    ));
+    for length in [1200, 1600, 2000, 2500, 3000] {
+        lib.add_component(connector(
+            &format!("Bolt-M8-{}", length / 100),
+            ExtrudeConnectorData::Bolt(BoltData::Extrude(ExtrudeBoltData {
+                extrude_bolt_type: ExtrudeBoltType::T,
+                hole: Hole::M8,
+                bolt_length: length,
+            })),
+        ));
+    }
+    lib
+}
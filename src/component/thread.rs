@@ -0,0 +1,74 @@
+use crate::{assembly_node::ScrewType, unit::LENGTH_UNIT};
+
+use super::{
+    extrude::Drill,
+    extrude_connector::{BoltData, ExtrudeConnectorData, Hole, NutData},
+    ComponentData,
+};
+
+// 普通螺母高度 / 公称直径
+const NUT_HEIGHT_RATIO: f32 = 0.8;
+
+impl Drill {
+    // 型材端面攻牙 (螺纹, 深度 0.01mm)
+    fn thread(&self) -> (ScrewType, u32) {
+        match self {
+            Drill::M6_15mm => (ScrewType::M6, 1500),
+            Drill::M8_20mm => (ScrewType::M8, 2000),
+            Drill::M8_25mm => (ScrewType::M8, 2500),
+            Drill::M12_30mm => (ScrewType::M12, 3000),
+            Drill::M14_30mm => (ScrewType::M14, 3000),
+        }
+    }
+}
+
+impl Hole {
+    // 光孔 M7 没有螺纹
+    fn screw_type(&self) -> Option<ScrewType> {
+        match self {
+            Hole::M4 => Some(ScrewType::M4),
+            Hole::M5 => Some(ScrewType::M5),
+            Hole::M6 => Some(ScrewType::M6),
+            Hole::M7 => None,
+            Hole::M8 => Some(ScrewType::M8),
+            Hole::M10 => Some(ScrewType::M10),
+            Hole::M12 => Some(ScrewType::M12),
+        }
+    }
+}
+
+impl ComponentData {
+    /// tapped hole drilled into an end of an extrusion: (thread, depth 0.01mm)
+    pub(crate) fn end_tap(&self) -> Option<(ScrewType, u32)> {
+        match self {
+            ComponentData::Extrude(e) => Some(e.post_process.drill.thread()),
+            _ => None,
+        }
+    }
+
+    /// internal thread of a nut: (thread, height 0.01mm)
+    pub(crate) fn nut_thread(&self) -> Option<(ScrewType, u32)> {
+        match self {
+            ComponentData::ExtrudeConnector(ExtrudeConnectorData::Nut(NutData::Extrude(n))) => {
+                Some((n.hole.screw_type()?, n.series.height()))
+            }
+            ComponentData::ExtrudeConnector(ExtrudeConnectorData::Nut(NutData::Normal(n))) => {
+                let screw_type = n.hole.screw_type()?;
+                let height = screw_type.diameter() * NUT_HEIGHT_RATIO * LENGTH_UNIT;
+                Some((screw_type, height.round() as u32))
+            }
+            _ => None,
+        }
+    }
+
+    /// external thread of a bolt from under its head: (thread, length 0.01mm)
+    pub(crate) fn bolt_thread(&self) -> Option<(ScrewType, u32)> {
+        match self {
+            ComponentData::ExtrudeConnector(ExtrudeConnectorData::Bolt(BoltData::Extrude(b))) => {
+                Some((b.hole.screw_type()?, b.bolt_length))
+            }
+            // 普通螺栓只有适配槽宽, 没有螺纹规格
+            _ => None,
+        }
+    }
+}
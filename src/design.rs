@@ -1,16 +1,57 @@
+mod align;
+mod buffer;
+mod canonical;
+mod change;
+mod clipboard;
+mod diff;
+mod extend;
+mod fastener;
+mod history;
+mod id;
+mod interference;
+pub(crate) mod joint;
+mod log;
+mod merge;
+mod mirror;
 mod operation;
-use std::collections::HashMap;
+mod pattern;
+mod spatial;
+mod split;
+mod thread;
+mod validate;
+use std::collections::{HashMap, HashSet};
 
+use nalgebra::{Point3, Vector3};
 use uuid::Uuid;
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::instance::Instance;
+use crate::{
+    component::{BoltRecommendation, ClampStack, Component, ComponentLib},
+    instance::{Aabb, Instance},
+    Translation,
+};
 
+use buffer::TransformBuffer;
+use change::ChangeSet;
+use clipboard::Clipboard;
+use diff::DesignDiff;
+use fastener::BoltWarnings;
+use history::History;
+use id::IdGenerator;
+use interference::Interferences;
+use joint::Joint;
+use log::{LogEntry, OperationLog};
+use merge::MergeConflicts;
 use operation::{DesignOperation, Operation};
+use pattern::{PatternFeature, PatternFeatures};
+use spatial::SpatialIndex;
+use thread::ThreadWarnings;
+use validate::OperationError;
 
 trait Record {
     type Operation;
-    fn push(&mut self, edit: Self::Operation);
+    type Error;
+    fn push(&mut self, edit: Self::Operation) -> Result<(), Self::Error>;
 
     // ctrl+z
     fn pop(&mut self) -> Option<&Self::Operation>;
@@ -23,10 +64,34 @@ trait Record {
 #[derive(Debug)]
 pub struct DesignSpace {
     instances: HashMap<Uuid, Instance>,
+    components: ComponentLib,
+    joints: HashMap<Uuid, Joint>,
+    index: SpatialIndex,
     // constraints: Vec<ConstraintSystem>,
     records: Vec<DesignOperation>,
 
     poped: Vec<DesignOperation>,
+
+    // operations applied since begin_transaction, recorded as one Batch on commit
+    transaction: Option<Vec<DesignOperation>>,
+
+    // records.len() when the current gesture began
+    gesture: Option<usize>,
+
+    log: OperationLog,
+    replaying: bool,
+
+    ids: IdGenerator,
+
+    // 协同编辑时的副本编号, 同一时刻的修改以编号大者为准
+    site: u32,
+
+    // 按零件缓存的实例变换, dirty 中的零件需要重新生成
+    buffers: HashMap<String, TransformBuffer>,
+    dirty: HashSet<String>,
+
+    // 阵列特征, 副本由阵列操作生成
+    patterns: HashMap<Uuid, PatternFeature>,
 }
 
 #[wasm_bindgen]
@@ -35,14 +100,88 @@ impl DesignSpace {
     pub fn new() -> Self {
         DesignSpace {
             instances: HashMap::new(),
+            components: ComponentLib::new(),
+            joints: HashMap::new(),
+            index: SpatialIndex::new(),
             records: Vec::new(),
             poped: Vec::new(),
+            transaction: None,
+            gesture: None,
+            log: OperationLog::default(),
+            replaying: false,
+            ids: IdGenerator::default(),
+            site: 0,
+            buffers: HashMap::new(),
+            dirty: HashSet::new(),
+            patterns: HashMap::new(),
         }
     }
 
     pub fn get_instances(&self) -> Vec<Instance> {
         self.instances.values().cloned().collect()
     }
+
+    pub fn get_joints(&self) -> Vec<Joint> {
+        self.joints.values().cloned().collect()
+    }
+
+    pub fn add_component(&mut self, component: &Component) {
+        self.components.add_component(component.clone());
+        let ids: Vec<Uuid> = self
+            .instances
+            .values()
+            .filter(|i| i.component_label == component.label)
+            .map(|i| i.id)
+            .collect();
+        self.refresh_index(&ids);
+        self.append_log(LogEntry::AddComponent(component.clone()));
+    }
+
+    /// labels of the registered connectors which fit the slot of the profile `label`
+    pub fn compatible_connectors(&self, label: String) -> Vec<String> {
+        self.components
+            .compatible_connectors(&label)
+            .into_iter()
+            .map(|c| c.label.clone())
+            .collect()
+    }
+
+    /// bolt length range of a clamp stack and the nearest registered bolt
+    pub fn recommend_bolt(&self, stack: ClampStack) -> Result<BoltRecommendation, String> {
+        self.components.recommend_bolt(&stack)
+    }
+
+    /// bolted joints whose bolt is too short or too long for its clamp stack, call after each edit
+    pub fn bolt_warnings(&self) -> BoltWarnings {
+        BoltWarnings(self.find_bolt_warnings())
+    }
+
+    /// bolts jointed to a threaded hole with too little engagement or bottoming out, call after each edit
+    pub fn thread_warnings(&self) -> ThreadWarnings {
+        ThreadWarnings(self.find_thread_warnings())
+    }
+
+    /// instance pairs occupying the same space, call after each edit
+    pub fn interferences(&self) -> Interferences {
+        Interferences(self.find_interferences())
+    }
+}
+
+impl DesignSpace {
+    /// sync the spatial index with the current state of `ids`
+    fn refresh_index(&mut self, ids: &[Uuid]) {
+        self.mark_dirty(ids);
+        for id in ids {
+            let obb = self
+                .instances
+                .get(id)
+                .and_then(|i| i.bounding_box(self.components.components.get(&i.component_label)?));
+            match obb {
+                Some(obb) => self.index.update(*id, obb),
+                None => self.index.remove(id),
+            }
+        }
+    }
 }
 
 impl Default for DesignSpace {
@@ -53,19 +192,44 @@ impl Default for DesignSpace {
 
 impl Record for DesignSpace {
     type Operation = DesignOperation;
+    type Error = OperationError;
 
-    fn push(&mut self, mut edit: Self::Operation) {
-        edit.operate(self);
-        if self.records.is_empty() || !self.records.last_mut().unwrap().compress(&edit) {
-            self.records.push(edit);
+    fn push(&mut self, mut edit: Self::Operation) -> Result<(), Self::Error> {
+        // logged as it was before operate, entries are appended after the edit is applied
+        let entry = LogEntry::Push(edit.clone());
+        self.try_operate(&mut edit)?;
+        for id in edit.instance_ids().iter().chain(edit.joint_ids().iter()) {
+            self.ids.skip(id);
+        }
+        self.refresh_index(&edit.instance_ids());
+        if let Some(transaction) = &mut self.transaction {
+            transaction.push(edit);
+        } else {
+            // only edits of the same gesture are merged, e.g. one length-gizmo drag
+            let coalesce = self.gesture.is_some_and(|start| self.records.len() > start);
+            if !coalesce || !self.records.last_mut().unwrap().compress(&edit) {
+                self.records.push(edit);
+            }
             self.poped.clear();
         }
+        self.append_log(entry);
+        Ok(())
     }
 
     fn pop(&mut self) -> Option<&Self::Operation> {
-        if let Some(mut o) = self.records.pop() {
+        self.commit_transaction();
+        self.end_gesture();
+        let entry = LogEntry::Pop(self.records.last().cloned());
+        let poped = if let Some(mut o) = self.records.pop() {
             o.inverse(self);
+            self.refresh_index(&o.instance_ids());
             self.poped.push(o);
+            true
+        } else {
+            false
+        };
+        self.append_log(entry);
+        if poped {
             self.poped.last()
         } else {
             None
@@ -73,34 +237,220 @@ impl Record for DesignSpace {
     }
 
     fn repush(&mut self) {
+        self.commit_transaction();
+        self.end_gesture();
+        let entry = LogEntry::Repush(self.poped.last().cloned());
         if let Some(mut o) = self.poped.pop() {
             o.operate(self);
-            if self.records.is_empty() || !self.records.last_mut().unwrap().compress(&o) {
-                self.records.push(o);
-            }
+            self.refresh_index(&o.instance_ids());
+            self.records.push(o);
         }
+        self.append_log(entry);
     }
 }
 
 #[wasm_bindgen]
 impl DesignSpace {
-    pub fn push(&mut self, edit: DesignOperation) {
-        <Self as Record>::push(self, edit);
+    /// apply an edit, returns the instances it changed so only their meshes are updated.
+    /// An edit that does not fit the design is rejected and not recorded
+    pub fn push(&mut self, edit: DesignOperation) -> Result<ChangeSet, OperationError> {
+        let mut result = Ok(());
+        let changes = self.track(edit.instance_ids(), |s| {
+            result = <Self as Record>::push(s, edit)
+        });
+        result.map(|_| changes)
     }
 
-    pub fn pop(&mut self) {
-        <Self as Record>::pop(self);
+    pub fn pop(&mut self) -> ChangeSet {
+        self.commit_transaction();
+        let ids = self.records.last().map_or(Vec::new(), |o| o.instance_ids());
+        self.track(ids, |s| {
+            <Self as Record>::pop(s);
+        })
     }
 
-    pub fn repush(&mut self) {
-        <Self as Record>::repush(self);
+    pub fn repush(&mut self) -> ChangeSet {
+        self.commit_transaction();
+        let ids = self.poped.last().map_or(Vec::new(), |o| o.instance_ids());
+        self.track(ids, <Self as Record>::repush)
+    }
+
+    /// every call that changed the design since it was created, to rebuild it with `replay`
+    pub fn export_log(&self) -> OperationLog {
+        self.log.clone()
+    }
+
+    /// rebuild a design space from an exported log, fails if an entry no longer applies
+    pub fn replay(log: OperationLog) -> Result<DesignSpace, String> {
+        DesignSpace::from_log(log)
+    }
+
+    /// generate instance ids from `seed` instead of randomly, for reproducible tests and logs
+    pub fn use_sequential_ids(&mut self, seed: u64) {
+        self.ids = IdGenerator::sequential(seed);
+        self.append_log(LogEntry::SequentialIds(seed));
+    }
+
+    /// replica number for collaborative editing, same-time edits of the higher site win
+    pub fn set_site(&mut self, site: u32) {
+        self.site = site;
+        self.append_log(LogEntry::SetSite(site));
+    }
+
+    /// merge the edits `theirs` made after the first `base` records as one history entry,
+    /// both replicas converge when each merges the other
+    pub fn merge(&mut self, base: usize, theirs: &DesignSpace) -> Result<MergeConflicts, String> {
+        let (operations, conflicts) = self.merge_operations(base, theirs)?;
+        self.begin_transaction();
+        for op in operations {
+            if let Err(e) = <Self as Record>::push(self, op) {
+                self.rollback_transaction();
+                return Err(e.to_string());
+            }
+        }
+        self.commit_transaction();
+        Ok(MergeConflicts(conflicts))
+    }
+
+    /// what changed from this revision to `other`, for design reviews
+    pub fn diff(&self, other: &DesignSpace) -> DesignDiff {
+        self.diff_to(other)
+    }
+
+    /// copy the instances `ids` to a clipboard payload, paste it with `paste_clipboard`
+    pub fn copy(&self, ids: Vec<String>) -> Result<Clipboard, String> {
+        self.copy_to_clipboard(&ids)
+    }
+
+    /// pattern features whose count and pitch can be edited with `edit_pattern`
+    pub fn get_patterns(&self) -> PatternFeatures {
+        self.pattern_features()
+    }
+
+    /// component labels whose transform buffers changed since they were last fetched
+    pub fn dirty_labels(&self) -> Vec<String> {
+        let mut labels: Vec<String> = self.dirty.iter().cloned().collect();
+        labels.sort();
+        labels
+    }
+
+    /// packed transforms of every instance of a component, rebuilt only when dirty
+    pub fn transform_buffer(&mut self, label: String) -> TransformBuffer {
+        self.transform_buffer_of(&label).clone()
+    }
+
+    /// labels of the undoable and redoable operations, for a history panel
+    pub fn history(&self) -> History {
+        self.history_labels()
+    }
+
+    /// undo or redo until the first `index` operations of `history` are applied
+    pub fn jump_to(&mut self, index: usize) -> Result<ChangeSet, String> {
+        self.commit_transaction();
+        let redo = index.saturating_sub(self.records.len());
+        let ids = self
+            .records
+            .iter()
+            .skip(index)
+            .chain(self.poped.iter().rev().take(redo))
+            .flat_map(|o| o.instance_ids())
+            .collect();
+        let mut result = Ok(());
+        let changes = self.track(ids, |s| result = s.jump_history(index));
+        result.map(|_| changes)
+    }
+
+    /// start a continuous edit such as a gizmo drag, consecutive edits of the same
+    /// instance are merged into one history entry until end_gesture
+    pub fn begin_gesture(&mut self) {
+        self.gesture = Some(self.records.len());
+        self.append_log(LogEntry::BeginGesture);
+    }
+
+    pub fn end_gesture(&mut self) {
+        if self.gesture.take().is_some() {
+            self.append_log(LogEntry::EndGesture);
+        }
+    }
+
+    /// group the following pushes into one history entry, e.g. a multi-select edit
+    pub fn begin_transaction(&mut self) {
+        self.commit_transaction();
+        self.transaction = Some(Vec::new());
+        self.append_log(LogEntry::BeginTransaction);
+    }
+
+    pub fn commit_transaction(&mut self) {
+        if let Some(operations) = self.transaction.take() {
+            if !operations.is_empty() {
+                self.records
+                    .push(DesignOperation::Batch(operation::Batch { operations }));
+                self.poped.clear();
+            }
+            self.append_log(LogEntry::CommitTransaction);
+        }
+    }
+
+    /// undo the pushes since begin_transaction without recording them
+    pub fn rollback_transaction(&mut self) -> ChangeSet {
+        let ids = self
+            .transaction
+            .iter()
+            .flatten()
+            .flat_map(|o| o.instance_ids())
+            .collect();
+        self.track(ids, |s| {
+            if let Some(operations) = s.transaction.take() {
+                for mut o in operations.into_iter().rev() {
+                    o.inverse(s);
+                    s.refresh_index(&o.instance_ids());
+                }
+                s.append_log(LogEntry::RollbackTransaction);
+            }
+        })
+    }
+
+    /// id of the nearest instance hit by a ray, for picking
+    pub fn pick(&self, origin: Translation, direction: Translation) -> Option<String> {
+        self.index
+            .ray_pick(
+                &Point3::new(origin.x, origin.y, origin.z),
+                &Vector3::new(direction.x, direction.y, direction.z),
+            )
+            .map(|(id, _)| id.to_string())
+    }
+
+    /// ids of the instances intersecting an axis aligned box
+    pub fn select_box(&self, min: Translation, max: Translation) -> Vec<String> {
+        self.index
+            .select_box(&Aabb::new(
+                Point3::new(min.x, min.y, min.z),
+                Point3::new(max.x, max.y, max.z),
+            ))
+            .iter()
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    /// ids of the instances inside a frustum, `planes` holds (nx, ny, nz, constant) per plane
+    /// with normals pointing inward, as three.js `Frustum.planes`
+    pub fn select_frustum(&self, planes: Vec<f32>) -> Vec<String> {
+        let planes: Vec<(Vector3<f32>, f32)> = planes
+            .chunks_exact(4)
+            .map(|p| (Vector3::new(p[0], p[1], p[2]), p[3]))
+            .collect();
+        self.index
+            .select_frustum(&planes)
+            .iter()
+            .map(|id| id.to_string())
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        component::ComponentLib,
+        component::{ComponentLib, ComponentType},
         instance::{ExtrudeConfig, InstanceConfig},
         log,
         // log,
@@ -122,8 +472,13 @@ mod test {
         //     InstanceConfig::default_extrude(100000), // 1m
         // )
         // .unwrap();
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
-        design.push(DesignOperation::AddInstance(add));
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        design.push(DesignOperation::AddInstance(add)).unwrap();
         assert_eq!(design.instances.len(), 1);
         assert_eq!(design.records.len(), 1);
         // log(&format!("{:#?}", design));
@@ -134,8 +489,13 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
-        design.push(DesignOperation::AddInstance(add));
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        design.push(DesignOperation::AddInstance(add)).unwrap();
         design.pop();
         assert_eq!(design.instances.len(), 0);
         assert_eq!(design.records.len(), 0);
@@ -153,15 +513,23 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
 
         let remove = RemoveInstance {
             id,
             removed_instance: None,
+            removed_joints: Vec::new(),
         };
-        design.push(DesignOperation::RemoveInstance(remove));
+        design
+            .push(DesignOperation::RemoveInstance(remove))
+            .unwrap();
         assert_eq!(design.instances.len(), 0);
         assert_eq!(design.records.len(), 2);
         // log(&format!("{:#?}", design));
@@ -172,15 +540,23 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
 
         let remove = RemoveInstance {
             id,
             removed_instance: None,
+            removed_joints: Vec::new(),
         };
-        design.push(DesignOperation::RemoveInstance(remove));
+        design
+            .push(DesignOperation::RemoveInstance(remove))
+            .unwrap();
         assert_eq!(design.instances.len(), 0);
         assert_eq!(design.records.len(), 2);
         design.pop();
@@ -193,28 +569,57 @@ mod test {
         // log(&format!("{:#?}", design));
     }
 
+    #[wasm_bindgen_test]
+    fn remove_instance_joint_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let a = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let b = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let (a_instance, b_instance) = (a.instance.clone(), b.instance.clone());
+        design.push(DesignOperation::AddInstance(a)).unwrap();
+        design.push(DesignOperation::AddInstance(b)).unwrap();
+        let op = add_joint(&mut design, &a_instance, &b_instance);
+        design.push(op).unwrap();
+
+        // the joint goes with the instance and comes back on undo
+        design.push(remove_instance(&b_instance)).unwrap();
+        assert!(design.joints.is_empty());
+        design.pop();
+        assert_eq!(design.joints.len(), 1);
+        design.repush();
+        assert!(design.joints.is_empty());
+    }
+
     #[wasm_bindgen_test]
     fn config_instance_operate_test() {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::PostProcessInstance(PostProcessInstance {
-            id,
-            config: InstanceConfig::Extrude(ExtrudeConfig {
-                drill_left: true,
-                drill_right: false,
-                bevel_cut: None,
-                wrench_hole_left: None,
-                wrench_hole_right: None,
-                counterbore_left: 0,
-                counterbore_right: 0,
-                length: 10000,
-            }),
-            config_cache: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::PostProcessInstance(PostProcessInstance {
+                id,
+                config: InstanceConfig::Extrude(ExtrudeConfig {
+                    drill_left: true,
+                    drill_right: false,
+                    bevel_cut: None,
+                    wrench_hole_left: None,
+                    wrench_hole_right: None,
+                    counterbore_left: 0,
+                    counterbore_right: 0,
+                    length: 10000,
+                }),
+                config_cache: None,
+            }))
+            .unwrap();
         // log(&format!("{:#?}", design));
     }
 
@@ -223,23 +628,30 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::PostProcessInstance(PostProcessInstance {
-            id,
-            config: InstanceConfig::Extrude(ExtrudeConfig {
-                drill_left: true,
-                drill_right: false,
-                bevel_cut: None,
-                wrench_hole_left: None,
-                wrench_hole_right: None,
-                counterbore_left: 0,
-                counterbore_right: 0,
-                length: 10000,
-            }),
-            config_cache: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::PostProcessInstance(PostProcessInstance {
+                id,
+                config: InstanceConfig::Extrude(ExtrudeConfig {
+                    drill_left: true,
+                    drill_right: false,
+                    bevel_cut: None,
+                    wrench_hole_left: None,
+                    wrench_hole_right: None,
+                    counterbore_left: 0,
+                    counterbore_right: 0,
+                    length: 10000,
+                }),
+                config_cache: None,
+            }))
+            .unwrap();
         design.pop();
         // log(&format!("{:#?}", design));
         design.repush();
@@ -251,22 +663,31 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
-            id,
-            dlength: 100000,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: 100000,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         // log(&format!("ExtrudeAddLength \n {:#?}", design));
-        design.push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
-            id,
-            dlength: -100000,
-            new_matrix: Isometry3::translation(100.0, 100.0, 200.0),
-            old_matrix: None,
-        }));
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: -100000,
+                new_matrix: Isometry3::translation(100.0, 100.0, 200.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         // log(&format!("ExtrudeAddLength \n {:#?}", design));
     }
 
@@ -275,15 +696,22 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
-            id,
-            dlength: 100000,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: 100000,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         design.pop();
         // log(&format!("ExtrudeAddLength {:#?}", design));
         design.repush();
@@ -295,21 +723,30 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
-            id,
-            dlength: 100000,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
-        design.push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
-            id,
-            dlength: 100000,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: 100000,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: 100000,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         // log(&format!("{:#?}", design));
     }
 
@@ -318,14 +755,21 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::MoveInstance(MoveInstance {
-            id,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         // log(&format!("{:#?}", design));
     }
 
@@ -334,14 +778,21 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::MoveInstance(MoveInstance {
-            id,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         design.pop();
         // log(&format!("{:#?}", design));
         design.repush();
@@ -353,19 +804,28 @@ mod test {
         let lib = ComponentLib::default();
         let mut design = DesignSpace::new();
 
-        let add = AddInstance::extrude(lib.components.get("LCF8-4040").unwrap(), 100000).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::MoveInstance(MoveInstance {
-            id,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
-        design.push(DesignOperation::MoveInstance(MoveInstance {
-            id,
-            new_matrix: Isometry3::translation(100.0, 100.0, 200.0),
-            old_matrix: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id,
+                new_matrix: Isometry3::translation(100.0, 100.0, 200.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         // log(&format!("{:#?}", design));
     }
 
@@ -375,6 +835,7 @@ mod test {
         let mut design = DesignSpace::new();
 
         let add = AddInstance::panel(
+            &mut design.ids,
             lib.components.get("WoodenPanel-test").unwrap(),
             200000,
             100000,
@@ -382,15 +843,17 @@ mod test {
         )
         .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::PanelAddSize(PanelAddSize {
-            id,
-            dx: 100,
-            dy: 100,
-            dthickness: 10,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::PanelAddSize(PanelAddSize {
+                id,
+                dx: 100,
+                dy: 100,
+                dthickness: 10,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         // log(&format!("{:#?}", design));
     }
 
@@ -400,6 +863,7 @@ mod test {
         let mut design = DesignSpace::new();
 
         let add = AddInstance::panel(
+            &mut design.ids,
             lib.components.get("WoodenPanel-test").unwrap(),
             200000,
             100000,
@@ -407,15 +871,17 @@ mod test {
         )
         .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::PanelAddSize(PanelAddSize {
-            id,
-            dx: 100,
-            dy: 100,
-            dthickness: 10,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
-            old_matrix: None,
-        }));
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::PanelAddSize(PanelAddSize {
+                id,
+                dx: 100,
+                dy: 100,
+                dthickness: 10,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
         design.pop();
         // log(&format!("{:#?}", design));
         design.repush();
@@ -428,6 +894,7 @@ mod test {
         let mut design = DesignSpace::new();
 
         let add = AddInstance::panel(
+            &mut design.ids,
             lib.components.get("WoodenPanel-test").unwrap(),
             200000,
             100000,
@@ -435,23 +902,416 @@ mod test {
         )
         .unwrap();
         let id = add.instance.id;
-        design.push(DesignOperation::AddInstance(add));
-        design.push(DesignOperation::PanelAddSize(PanelAddSize {
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::PanelAddSize(PanelAddSize {
+                id,
+                dx: 100,
+                dy: 100,
+                dthickness: 10,
+                new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design
+            .push(DesignOperation::PanelAddSize(PanelAddSize {
+                id,
+                dx: 100,
+                dy: 100,
+                dthickness: 10,
+                new_matrix: Isometry3::translation(100.0, 100.0, 200.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        log(&format!("{:#?}", design));
+    }
+
+    fn move_op(id: Uuid, x: f64) -> DesignOperation {
+        DesignOperation::MoveInstance(MoveInstance {
             id,
-            dx: 100,
-            dy: 100,
-            dthickness: 10,
-            new_matrix: Isometry3::translation(100.0, 100.0, 100.0),
+            new_matrix: Isometry3::translation(x, 0.0, 0.0),
             old_matrix: None,
-        }));
-        design.push(DesignOperation::PanelAddSize(PanelAddSize {
+        })
+    }
+
+    #[wasm_bindgen_test]
+    fn no_gesture_no_compress_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let id = add.instance.id;
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design.push(move_op(id, 1.0)).unwrap();
+        design.push(move_op(id, 2.0)).unwrap();
+        assert_eq!(design.records.len(), 3);
+        design.pop();
+        assert_eq!(
+            design.instances[&id].matrix,
+            Isometry3::translation(1.0, 0.0, 0.0)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn gesture_compress_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+
+        let a = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let b = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let (a_id, b_id) = (a.instance.id, b.instance.id);
+        design.push(DesignOperation::AddInstance(a)).unwrap();
+        design.push(DesignOperation::AddInstance(b)).unwrap();
+
+        // drag a, then drag b
+        design.begin_gesture();
+        for x in [1.0, 2.0, 3.0] {
+            design.push(move_op(a_id, x)).unwrap();
+        }
+        design.end_gesture();
+        design.begin_gesture();
+        for x in [4.0, 5.0] {
+            design.push(move_op(b_id, x)).unwrap();
+        }
+        design.end_gesture();
+        assert_eq!(design.records.len(), 4);
+
+        design.pop();
+        assert_eq!(
+            design.instances[&a_id].matrix,
+            Isometry3::translation(3.0, 0.0, 0.0)
+        );
+        assert_eq!(design.instances[&b_id].matrix, Isometry3::identity());
+        design.pop();
+        assert_eq!(design.instances[&a_id].matrix, Isometry3::identity());
+        design.repush();
+        design.repush();
+        assert_eq!(design.records.len(), 4);
+        assert_eq!(
+            design.instances[&a_id].matrix,
+            Isometry3::translation(3.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            design.instances[&b_id].matrix,
+            Isometry3::translation(5.0, 0.0, 0.0)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn gesture_compress_by_id_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let a_id = add.instance.id;
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let b_id = add.instance.id;
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+
+        // a gesture never merges edits of different instances, nor into earlier records
+        design.begin_gesture();
+        design.push(move_op(a_id, 1.0)).unwrap();
+        design.push(move_op(b_id, 2.0)).unwrap();
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id: a_id,
+                dlength: 1000,
+                new_matrix: Isometry3::translation(1.0, 0.0, 0.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id: b_id,
+                dlength: 1000,
+                new_matrix: Isometry3::translation(2.0, 0.0, 0.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design.end_gesture();
+        assert_eq!(design.records.len(), 6);
+
+        for _ in 0..4 {
+            design.pop();
+        }
+        for id in [a_id, b_id] {
+            assert_eq!(design.instances[&id].matrix, Isometry3::identity());
+            assert_eq!(
+                design.instances[&id].config,
+                InstanceConfig::default_extrude(100000)
+            );
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn sequential_ids_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let ids = |seed| {
+            let mut design = DesignSpace::new();
+            design.use_sequential_ids(seed);
+            let a = add_extrude_instance(&mut design, component, 100000).unwrap();
+            design.push(a).unwrap();
+            let b = add_extrude_instance(&mut design, component, 100000).unwrap();
+            design.push(b).unwrap();
+            let mut ids: Vec<Uuid> = design.instances.keys().copied().collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(3), ids(3));
+        assert_eq!(ids(3)[1], Uuid::from_u64_pair(3, 1));
+        assert_ne!(ids(3), ids(4));
+
+        // joints take their ids from the same generator
+        let mut design = DesignSpace::new();
+        design.use_sequential_ids(3);
+        let a = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let b = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let joint = add_joint(&mut design, &a.instance, &b.instance);
+        design
+            .push(DesignOperation::Batch(Batch {
+                operations: vec![
+                    DesignOperation::AddInstance(a),
+                    DesignOperation::AddInstance(b),
+                    joint,
+                ],
+            }))
+            .unwrap();
+        assert_eq!(design.get_joints()[0].id, Uuid::from_u64_pair(3, 2));
+    }
+
+    #[wasm_bindgen_test]
+    fn batch_operate_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+
+        let a = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let b = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let (a_id, b_id) = (a.instance.id, b.instance.id);
+        design
+            .push(DesignOperation::Batch(Batch {
+                operations: vec![
+                    DesignOperation::AddInstance(a),
+                    DesignOperation::AddInstance(b),
+                ],
+            }))
+            .unwrap();
+        assert_eq!(design.instances.len(), 2);
+        assert_eq!(design.records.len(), 1);
+
+        design
+            .push(DesignOperation::Batch(Batch {
+                operations: vec![
+                    DesignOperation::MoveInstance(MoveInstance {
+                        id: a_id,
+                        new_matrix: Isometry3::translation(1.0, 0.0, 0.0),
+                        old_matrix: None,
+                    }),
+                    DesignOperation::MoveInstance(MoveInstance {
+                        id: b_id,
+                        new_matrix: Isometry3::translation(2.0, 0.0, 0.0),
+                        old_matrix: None,
+                    }),
+                    DesignOperation::MoveInstance(MoveInstance {
+                        id: a_id,
+                        new_matrix: Isometry3::translation(3.0, 0.0, 0.0),
+                        old_matrix: None,
+                    }),
+                ],
+            }))
+            .unwrap();
+        assert_eq!(
+            design.instances[&a_id].matrix,
+            Isometry3::translation(3.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            design.instances[&b_id].matrix,
+            Isometry3::translation(2.0, 0.0, 0.0)
+        );
+
+        design.pop();
+        assert_eq!(design.instances[&a_id].matrix, Isometry3::identity());
+        assert_eq!(design.instances[&b_id].matrix, Isometry3::identity());
+        design.repush();
+        assert_eq!(
+            design.instances[&a_id].matrix,
+            Isometry3::translation(3.0, 0.0, 0.0)
+        );
+        design.pop();
+        design.pop();
+        assert_eq!(design.instances.len(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn transaction_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+
+        let a = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let b = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let (a_id, b_id) = (a.instance.id, b.instance.id);
+        design.push(DesignOperation::AddInstance(a)).unwrap();
+        design.push(DesignOperation::AddInstance(b)).unwrap();
+
+        design.begin_transaction();
+        for (id, x) in [(a_id, 1.0), (b_id, 2.0)] {
+            design
+                .push(DesignOperation::MoveInstance(MoveInstance {
+                    id,
+                    new_matrix: Isometry3::translation(x, 0.0, 0.0),
+                    old_matrix: None,
+                }))
+                .unwrap();
+        }
+        design.commit_transaction();
+        assert_eq!(design.records.len(), 3);
+        assert_eq!(
+            design.instances[&b_id].matrix,
+            Isometry3::translation(2.0, 0.0, 0.0)
+        );
+
+        // one ctrl+z undoes the whole multi-select move
+        design.pop();
+        assert_eq!(design.records.len(), 2);
+        assert_eq!(design.instances[&a_id].matrix, Isometry3::identity());
+        assert_eq!(design.instances[&b_id].matrix, Isometry3::identity());
+
+        // an empty transaction records nothing, a rolled back one leaves no trace
+        design.begin_transaction();
+        design.commit_transaction();
+        design.begin_transaction();
+        design
+            .push(DesignOperation::RemoveInstance(RemoveInstance {
+                id: a_id,
+                removed_instance: None,
+                removed_joints: Vec::new(),
+            }))
+            .unwrap();
+        design.rollback_transaction();
+        assert_eq!(design.records.len(), 2);
+        assert_eq!(design.poped.len(), 1);
+        assert_eq!(design.instances.len(), 2);
+    }
+
+    #[wasm_bindgen_test]
+    fn replace_component_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let wide = lib.components.get("LCF8-4080").unwrap();
+        let panel = lib.components.get("WoodenPanel-test").unwrap();
+        let mut design = DesignSpace::new();
+        design.add_component(bar);
+        design.add_component(wide);
+        let op = add_extrude_instance(&mut design, bar, 100000).unwrap();
+        let id = op.instance_ids()[0];
+        design.push(op).unwrap();
+        let mut config = match InstanceConfig::default_extrude(100000) {
+            InstanceConfig::Extrude(e) => e,
+            _ => unreachable!(),
+        };
+        config.drill_left = true;
+        config.counterbore_right = 2;
+        let op = extrude_post_process(&design.instances[&id], bar, config.clone()).unwrap();
+        design.push(op).unwrap();
+        let matrix = Isometry3::translation(1.0, 2.0, 3.0);
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id,
+                new_matrix: matrix,
+                old_matrix: None,
+            }))
+            .unwrap();
+        let before = design.instances[&id].clone();
+
+        let op = replace_component(&before, wide).unwrap();
+        let changes = design.push(op).unwrap();
+        assert!(changes.updated[0].component && !changes.updated[0].matrix);
+        let instance = &design.instances[&id];
+        assert_eq!(instance.component_label, "LCF8-4080");
+        assert_eq!(instance.matrix, matrix);
+        assert_eq!(instance.config, InstanceConfig::Extrude(config));
+        assert_eq!(
+            design.history().labels[3],
+            "Replace LCF8-4040 with LCF8-4080"
+        );
+
+        design.pop();
+        assert_eq!(design.instances[&id], before);
+        design.repush();
+        assert_eq!(design.instances[&id].component_label, "LCF8-4080");
+
+        // the new component has to be registered first
+        let op = replace_component(&before, panel).unwrap();
+        assert_eq!(
+            design.push(op),
+            Err(OperationError::MissingComponent(panel.label.clone()))
+        );
+        design.add_component(panel);
+
+        // an extrusion config does not fit a panel
+        let op = DesignOperation::ReplaceComponent(ReplaceComponent {
             id,
-            dx: 100,
-            dy: 100,
-            dthickness: 10,
-            new_matrix: Isometry3::translation(100.0, 100.0, 200.0),
-            old_matrix: None,
-        }));
-        log(&format!("{:#?}", design));
+            component_label: panel.label.clone(),
+            component_type: ComponentType::Panel,
+            config: before.config.clone(),
+            old_component: None,
+        });
+        assert_eq!(
+            design.push(op),
+            Err(OperationError::ConfigMismatch(id.to_string()))
+        );
+        // swapping to another kind of component starts from its default config
+        let op = replace_component(&before, panel).unwrap();
+        design.push(op).unwrap();
+        assert!(matches!(
+            design.instances[&id].config,
+            InstanceConfig::Panel(_)
+        ));
     }
 }
@@ -0,0 +1,223 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use uuid::Uuid;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{instance::Aabb, unit::snap_position};
+
+use super::{
+    id::parse_ids,
+    operation::{Batch, DesignOperation, MoveInstance},
+    DesignSpace,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+// 对齐到包围盒的最小端, 中心或最大端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum AlignMode {
+    Min,
+    Center,
+    Max,
+}
+
+impl Axis {
+    fn index(self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        }
+    }
+}
+
+impl AlignMode {
+    fn of(self, aabb: &Aabb, i: usize) -> f32 {
+        match self {
+            AlignMode::Min => aabb.min[i],
+            AlignMode::Center => (aabb.min[i] + aabb.max[i]) / 2.,
+            AlignMode::Max => aabb.max[i],
+        }
+    }
+}
+
+impl DesignSpace {
+    /// world bounding boxes of the instances `ids`, in the given order
+    fn aabbs(&self, ids: &[Uuid]) -> Result<Vec<Aabb>, String> {
+        ids.iter()
+            .map(|id| {
+                let instance = self
+                    .instances
+                    .get(id)
+                    .ok_or_else(|| format!("instance not found: {}", id))?;
+                self.components
+                    .components
+                    .get(&instance.component_label)
+                    .and_then(|c| instance.bounding_box(c))
+                    .map(|obb| obb.aabb())
+                    .ok_or_else(|| format!("unknown size of instance: {}", id))
+            })
+            .collect()
+    }
+
+    /// move `id` by `d` m along axis `i`, None if it stays
+    fn shift(&self, id: &Uuid, i: usize, d: f32) -> Option<DesignOperation> {
+        if d.abs() < f32::EPSILON {
+            return None;
+        }
+        let mut new_matrix = self.instances[id].matrix;
+        let t = &mut new_matrix.translation.vector[i];
+        *t = snap_position(*t + d as f64);
+        Some(DesignOperation::MoveInstance(MoveInstance {
+            id: *id,
+            new_matrix,
+            old_matrix: None,
+        }))
+    }
+}
+
+/// align the bounding boxes of `ids` along `axis`, to the face or centre of `reference`
+/// if given, otherwise to that of the whole selection
+#[wasm_bindgen]
+pub fn align_instances(
+    design: &DesignSpace,
+    ids: Vec<String>,
+    axis: Axis,
+    mode: AlignMode,
+    reference: Option<String>,
+) -> Result<DesignOperation, String> {
+    let ids = parse_ids(&ids)?;
+    let aabbs = design.aabbs(&ids)?;
+    let target = match reference {
+        Some(reference) => design.aabbs(&parse_ids(&[reference])?)?[0],
+        None => aabbs
+            .iter()
+            .copied()
+            .reduce(|a, b| a.merge(&b))
+            .ok_or_else(|| "nothing to align".to_string())?,
+    };
+    let i = axis.index();
+    let to = mode.of(&target, i);
+    Ok(DesignOperation::Batch(Batch {
+        operations: ids
+            .iter()
+            .zip(aabbs.iter())
+            .filter_map(|(id, aabb)| design.shift(id, i, to - mode.of(aabb, i)))
+            .collect(),
+    }))
+}
+
+/// space the bounding boxes of `ids` with equal gaps along `axis`, the first and the
+/// last one stay where they are
+#[wasm_bindgen]
+pub fn distribute_instances(
+    design: &DesignSpace,
+    ids: Vec<String>,
+    axis: Axis,
+) -> Result<DesignOperation, String> {
+    let ids = parse_ids(&ids)?;
+    if ids.len() < 3 {
+        return Err("distribute needs at least 3 instances".to_string());
+    }
+    let i = axis.index();
+    let aabbs = design.aabbs(&ids)?;
+    let mut boxes: Vec<(Uuid, Aabb)> = ids.into_iter().zip(aabbs).collect();
+    boxes.sort_by(|(_, a), (_, b)| {
+        AlignMode::Center
+            .of(a, i)
+            .total_cmp(&AlignMode::Center.of(b, i))
+    });
+    let size = |aabb: &Aabb| aabb.max[i] - aabb.min[i];
+    let (first, last) = (&boxes[0].1, &boxes[boxes.len() - 1].1);
+    let gap = (last.max[i] - first.min[i] - boxes.iter().map(|(_, b)| size(b)).sum::<f32>())
+        / (boxes.len() - 1) as f32;
+
+    let mut operations = Vec::new();
+    let mut at = first.max[i] + gap;
+    for (id, aabb) in boxes[1..boxes.len() - 1].iter() {
+        operations.extend(design.shift(id, i, at - aabb.min[i]));
+        at += size(aabb) + gap;
+    }
+    Ok(DesignOperation::Batch(Batch { operations }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{component::ComponentLib, design::operation::add_extrude_instance};
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn x(design: &DesignSpace, id: &Uuid) -> f64 {
+        design.instances[id].matrix.translation.x
+    }
+
+    #[wasm_bindgen_test]
+    fn align_distribute_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        design.add_component(bar);
+        // bars along x, 1m, 0.5m, 0.8m and 1m long
+        let mut ids = Vec::new();
+        for (length, at) in [(100000, 0.0), (50000, 3.0), (80000, 1.0), (100000, 0.3)] {
+            let mut op = add_extrude_instance(&mut design, bar, length).unwrap();
+            if let DesignOperation::AddInstance(op) = &mut op {
+                op.instance.matrix = Isometry3::translation(at, at, 0.0);
+            }
+            ids.push(op.instance_ids()[0]);
+            design.push(op).unwrap();
+        }
+        let strings: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+
+        // left faces line up with the leftmost one at x = -0.5
+        let op = align_instances(
+            &design,
+            strings[..3].to_vec(),
+            Axis::X,
+            AlignMode::Min,
+            None,
+        )
+        .unwrap();
+        design.push(op).unwrap();
+        assert!((x(&design, &ids[1]) + 0.25).abs() < 1e-6);
+        assert!((x(&design, &ids[2]) + 0.1).abs() < 1e-6);
+        assert_eq!(x(&design, &ids[0]), 0.0);
+        design.pop();
+
+        // centres line up with the reference, which does not move
+        let op = align_instances(
+            &design,
+            strings[..3].to_vec(),
+            Axis::Y,
+            AlignMode::Center,
+            Some(strings[3].clone()),
+        )
+        .unwrap();
+        design.push(op).unwrap();
+        for id in ids.iter() {
+            assert!((design.instances[id].matrix.translation.y - 0.3).abs() < 1e-6);
+        }
+        design.pop();
+
+        // equal gaps of 0.725m between -0.5..0.5 and 2.75..3.25 along x
+        let op = distribute_instances(&design, strings[..3].to_vec(), Axis::X).unwrap();
+        design.push(op).unwrap();
+        assert!((x(&design, &ids[2]) - 1.625).abs() < 1e-6);
+        assert_eq!((x(&design, &ids[0]), x(&design, &ids[1])), (0.0, 3.0));
+        assert_eq!(design.records.len(), 5);
+        design.pop();
+        assert_eq!(x(&design, &ids[2]), 1.0);
+        assert!(distribute_instances(&design, strings[..2].to_vec(), Axis::X).is_err());
+    }
+}
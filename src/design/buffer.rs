@@ -0,0 +1,166 @@
+use std::rc::Rc;
+
+use js_sys::Float32Array;
+use uuid::Uuid;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::instance::{Instance, InstanceConfig, LENGTH_UNIT};
+
+use super::DesignSpace;
+
+// 同一零件的全部实例, 供 three.js InstancedMesh 直接使用
+// 数组只在重建时生成一次, 克隆只复制引用
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransformBuffer {
+    ids: Rc<[Uuid]>,
+    matrices: Rc<[f32]>, // 每个实例 16 个, 列主序, 与 Matrix4.fromArray 一致 m
+    scales: Rc<[f32]>,   // 每个实例 3 个, 型材长度与面板尺寸 m
+}
+
+#[wasm_bindgen]
+impl TransformBuffer {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    /// copied straight from wasm memory into a new js array
+    pub fn matrices(&self) -> Float32Array {
+        Float32Array::from(&self.matrices[..])
+    }
+
+    /// copied straight from wasm memory into a new js array
+    pub fn scales(&self) -> Float32Array {
+        Float32Array::from(&self.scales[..])
+    }
+}
+
+impl TransformBuffer {
+    fn build(mut instances: Vec<&Instance>) -> Self {
+        instances.sort_by_key(|i| i.id);
+        let mut ids = Vec::with_capacity(instances.len());
+        let mut matrices = Vec::with_capacity(instances.len() * 16);
+        let mut scales = Vec::with_capacity(instances.len() * 3);
+        for i in instances {
+            ids.push(i.id);
+            matrices.extend_from_slice(i.isometry().to_homogeneous().as_slice());
+            let scale = match &i.config {
+                InstanceConfig::Extrude(e) => [e.length as f32 / LENGTH_UNIT, 1., 1.],
+                InstanceConfig::Panel(p) => [
+                    p.x as f32 / LENGTH_UNIT,
+                    p.thickness as f32 / LENGTH_UNIT,
+                    p.y as f32 / LENGTH_UNIT,
+                ],
+                InstanceConfig::Normal => [1., 1., 1.],
+            };
+            scales.extend_from_slice(&scale);
+        }
+        TransformBuffer {
+            ids: ids.into(),
+            matrices: matrices.into(),
+            scales: scales.into(),
+        }
+    }
+}
+
+impl DesignSpace {
+    /// mark the buffers holding `ids` before and after an edit as stale
+    pub(crate) fn mark_dirty(&mut self, ids: &[Uuid]) {
+        for id in ids {
+            if let Some(instance) = self.instances.get(id) {
+                self.dirty.insert(instance.component_label.clone());
+            }
+            for (label, buffer) in self.buffers.iter() {
+                if buffer.ids.binary_search(id).is_ok() {
+                    self.dirty.insert(label.clone());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn transform_buffer_of(&mut self, label: &str) -> &TransformBuffer {
+        if self.dirty.remove(label) || !self.buffers.contains_key(label) {
+            let instances = self
+                .instances
+                .values()
+                .filter(|i| i.component_label == label)
+                .collect();
+            self.buffers
+                .insert(label.to_string(), TransformBuffer::build(instances));
+        }
+        &self.buffers[label]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{
+            add_extrude_instance, add_panel_instance, DesignOperation, MoveInstance, RemoveInstance,
+        },
+    };
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn transform_buffer_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let panel = lib.components.get("WoodenPanel-test").unwrap();
+        let mut design = DesignSpace::new();
+        design.use_sequential_ids(1);
+        let mut ids = Vec::new();
+        for length in [100000, 50000] {
+            let op = add_extrude_instance(&mut design, bar, length).unwrap();
+            ids.extend(op.instance_ids());
+            design.push(op).unwrap();
+        }
+        let op = add_panel_instance(&mut design, panel, 100000, 50000, 2000).unwrap();
+        design.push(op).unwrap();
+
+        let buffer = design.transform_buffer("LCF8-4040".into());
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.matrices.len(), 32);
+        assert_eq!(*buffer.scales, [1., 1., 1., 0.5, 1., 1.]);
+        let buffer = design.transform_buffer("WoodenPanel-test".into());
+        assert_eq!(*buffer.scales, [1., 0.02, 0.5]);
+        assert!(design.dirty_labels().is_empty());
+
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id: ids[1],
+                new_matrix: Isometry3::translation(1.0, 2.0, 3.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        assert_eq!(design.dirty_labels(), vec!["LCF8-4040".to_string()]);
+        let buffer = design.transform_buffer("LCF8-4040".into());
+        // translation in the last column
+        assert_eq!(buffer.matrices[28..31], [1., 2., 3.]);
+        assert!(design.dirty_labels().is_empty());
+
+        design
+            .push(DesignOperation::RemoveInstance(RemoveInstance {
+                id: ids[0],
+                removed_instance: None,
+                removed_joints: Vec::new(),
+            }))
+            .unwrap();
+        assert_eq!(design.dirty_labels(), vec!["LCF8-4040".to_string()]);
+        let buffer = design.transform_buffer("LCF8-4040".into());
+        assert_eq!(buffer.ids(), vec![ids[1].to_string()]);
+        design.pop();
+        assert_eq!(design.transform_buffer("LCF8-4040".into()).len(), 2);
+    }
+}
@@ -0,0 +1,239 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use nalgebra::{Matrix3, Rotation3, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use std::sync::OnceLock;
+
+use uuid::Uuid;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{unit::snap_position, Quaternion};
+
+use super::{
+    operation::{Batch, DesignOperation, MoveInstance},
+    DesignSpace,
+};
+
+// 与轴对齐朝向相差小于该角度时视为漂移 rad (0.5°)
+const ALIGN_TOLERANCE: f32 = 0.5 * std::f32::consts::PI / 180.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum SnapAngle {
+    Deg90,
+    Deg45,
+}
+
+/// the 24 orientations that map the axes onto the axes
+fn axis_aligned() -> &'static [UnitQuaternion<f32>] {
+    static ROTATIONS: OnceLock<Vec<UnitQuaternion<f32>>> = OnceLock::new();
+    ROTATIONS.get_or_init(|| {
+        let mut rotations = Vec::new();
+        for (x, y) in [(0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1)] {
+            for (sx, sy) in [(1., 1.), (1., -1.), (-1., 1.), (-1., -1.)] {
+                let mut u = Vector3::zeros();
+                let mut v = Vector3::zeros();
+                u[x] = sx;
+                v[y] = sy;
+                let m = Matrix3::from_columns(&[u, v, u.cross(&v)]);
+                rotations.push(UnitQuaternion::from_rotation_matrix(
+                    &Rotation3::from_matrix_unchecked(m),
+                ));
+            }
+        }
+        rotations
+    })
+}
+
+/// distinct rotations whose euler angles are multiples of `step`
+fn euler_grid(step: f32) -> Vec<UnitQuaternion<f32>> {
+    let n = (2. * std::f32::consts::PI / step).round() as i32;
+    let mut rotations: Vec<UnitQuaternion<f32>> = Vec::new();
+    for r in 0..n {
+        for p in 0..n {
+            for y in 0..n {
+                let rotation = UnitQuaternion::from_euler_angles(
+                    r as f32 * step,
+                    p as f32 * step,
+                    y as f32 * step,
+                );
+                // 万向锁下不同欧拉角会得到同一旋转
+                if rotations.iter().all(|r| r.angle_to(&rotation) > 1e-4) {
+                    rotations.push(rotation);
+                }
+            }
+        }
+    }
+    rotations
+}
+
+// 45° 网格去重后有 208 个旋转, 只生成一次
+fn euler_grid_45() -> &'static [UnitQuaternion<f32>] {
+    static ROTATIONS: OnceLock<Vec<UnitQuaternion<f32>>> = OnceLock::new();
+    ROTATIONS.get_or_init(|| euler_grid(std::f32::consts::FRAC_PI_4))
+}
+
+fn nearest(
+    rotation: &UnitQuaternion<f32>,
+    candidates: &[UnitQuaternion<f32>],
+) -> UnitQuaternion<f32> {
+    *candidates
+        .iter()
+        .min_by(|a, b| rotation.angle_to(a).total_cmp(&rotation.angle_to(b)))
+        .unwrap()
+}
+
+fn snap(rotation: &UnitQuaternion<f32>, angle: SnapAngle) -> UnitQuaternion<f32> {
+    match angle {
+        SnapAngle::Deg90 => nearest(rotation, axis_aligned()),
+        SnapAngle::Deg45 => nearest(rotation, euler_grid_45()),
+    }
+}
+
+/// the nearest rotation in steps of 90° or 45°, for rotation gizmos
+#[wasm_bindgen]
+pub fn snap_rotation(quat: Quaternion, angle: SnapAngle) -> Quaternion {
+    let rotation =
+        UnitQuaternion::new_normalize(nalgebra::Quaternion::new(quat.w, quat.i, quat.j, quat.k));
+    let r = snap(&rotation, angle);
+    Quaternion {
+        i: r.i,
+        j: r.j,
+        k: r.k,
+        w: r.w,
+    }
+}
+
+impl DesignSpace {
+    /// snap rotations that drifted off an axis-aligned orientation back onto it and
+    /// translations from older logs onto the position grid
+    pub(crate) fn canonicalize_operations(&self) -> Vec<DesignOperation> {
+        let aligned = axis_aligned();
+        let mut ids: Vec<&Uuid> = self.instances.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .filter_map(|id| {
+                let matrix = self.instances[id].matrix;
+                let mut new_matrix = matrix;
+                let drifted = matrix.rotation.cast::<f32>();
+                let rotation = nearest(&drifted, aligned);
+                if drifted.angle_to(&rotation) < ALIGN_TOLERANCE {
+                    new_matrix.rotation = rotation.cast();
+                }
+                new_matrix.translation.vector = matrix.translation.vector.map(snap_position);
+                (new_matrix != matrix).then_some(DesignOperation::MoveInstance(MoveInstance {
+                    id: *id,
+                    new_matrix,
+                    old_matrix: None,
+                }))
+            })
+            .collect()
+    }
+}
+
+/// one undoable step that removes the f32 drift of every instance placement
+#[wasm_bindgen]
+pub fn canonicalize_instances(design: &DesignSpace) -> Result<DesignOperation, String> {
+    let operations = design.canonicalize_operations();
+    if operations.is_empty() {
+        return Err("every placement is already canonical".to_string());
+    }
+    Ok(DesignOperation::Batch(Batch { operations }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{component::ComponentLib, design::operation::add_extrude_instance};
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn quat(r: UnitQuaternion<f32>) -> Quaternion {
+        Quaternion {
+            i: r.i,
+            j: r.j,
+            k: r.k,
+            w: r.w,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn snap_rotation_test() {
+        assert_eq!(axis_aligned().len(), 24);
+        assert_eq!(euler_grid(std::f32::consts::FRAC_PI_2).len(), 24);
+        let grid = euler_grid_45();
+        // 512 euler triples, 208 distinct rotations
+        assert_eq!(grid.len(), 208);
+        assert!(grid
+            .iter()
+            .enumerate()
+            .all(|(i, a)| grid[i + 1..].iter().all(|b| a.angle_to(b) > 1e-4)));
+        let drifted = UnitQuaternion::from_euler_angles(0.01, 1.58, -0.02);
+        let snapped = snap_rotation(quat(drifted), SnapAngle::Deg90);
+        let expected = UnitQuaternion::from_euler_angles(0., std::f32::consts::FRAC_PI_2, 0.);
+        assert!(
+            expected.angle_to(&UnitQuaternion::new_normalize(nalgebra::Quaternion::new(
+                snapped.w, snapped.i, snapped.j, snapped.k
+            ))) < 1e-5
+        );
+
+        let drifted = UnitQuaternion::from_euler_angles(0., 0., 0.8);
+        let snapped = snap(&drifted, SnapAngle::Deg45);
+        assert!((snapped.angle() - std::f32::consts::FRAC_PI_4).abs() < 1e-5);
+        let snapped = snap(&drifted, SnapAngle::Deg90);
+        assert!((snapped.angle() - std::f32::consts::FRAC_PI_2).abs() < 1e-5);
+
+        // off any axis: a quarter turn about z then 45° about x, slightly drifted
+        let exact =
+            UnitQuaternion::from_axis_angle(&Vector3::x_axis(), std::f32::consts::FRAC_PI_4)
+                * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f32::consts::FRAC_PI_2);
+        let drifted = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.05) * exact;
+        let snapped = snap(&drifted, SnapAngle::Deg45);
+        assert!(snapped.angle_to(&exact) < 1e-5);
+        // the x axis ends exactly 45° between y and z
+        let x = snapped * Vector3::x();
+        assert!((x.y - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+        assert!((x.z - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+    }
+
+    #[wasm_bindgen_test]
+    fn canonicalize_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        let mut ids = Vec::new();
+        let matrices = [
+            // drifted a quarter turn around z
+            Isometry3::new(
+                Vector3::new(0.1000004, 0.2, 0.3),
+                Vector3::z() * (std::f64::consts::FRAC_PI_2 + 0.001),
+            ),
+            // intentionally at 30°
+            Isometry3::new(Vector3::zeros(), Vector3::z() * std::f64::consts::FRAC_PI_6),
+        ];
+        for matrix in matrices {
+            let op = add_extrude_instance(&mut design, bar, 100000).unwrap();
+            let id = op.instance_ids()[0];
+            design.push(op).unwrap();
+            // placed as read from a log written before positions were snapped
+            design.instances.get_mut(&id).unwrap().matrix = matrix;
+            ids.push(id);
+        }
+
+        design
+            .push(canonicalize_instances(&design).unwrap())
+            .unwrap();
+        let m = design.instances[&ids[0]].matrix;
+        assert_eq!(m.translation.x, 0.1);
+        assert!((m.rotation * Vector3::x() - Vector3::y()).norm() < 1e-6);
+        assert_eq!(design.instances[&ids[1]].matrix, matrices[1]);
+        // canonical placements stay as they are
+        assert!(canonicalize_instances(&design).is_err());
+
+        // undo brings the drifted placement back exactly
+        design.pop();
+        assert_eq!(design.instances[&ids[0]].matrix, matrices[0]);
+    }
+}
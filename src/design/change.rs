@@ -0,0 +1,125 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use uuid::Uuid;
+
+use crate::instance::Instance;
+
+use super::DesignSpace;
+
+// 实例中发生变化的部分
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Updated {
+    pub id: String,
+    pub matrix: bool,
+    pub config: bool,
+    pub component: bool,
+}
+
+// 一次编辑影响的实例, 前端只需更新这些网格
+#[derive(Debug, Clone, Default, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ChangeSet {
+    pub created: Vec<String>,
+    pub updated: Vec<Updated>,
+    pub deleted: Vec<String>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+}
+
+impl DesignSpace {
+    /// run `f` and report how it changed the instances `ids`
+    pub(crate) fn track(&mut self, mut ids: Vec<Uuid>, f: impl FnOnce(&mut Self)) -> ChangeSet {
+        ids.sort();
+        ids.dedup();
+        let before: Vec<Option<Instance>> = ids
+            .iter()
+            .map(|id| self.instances.get(id).cloned())
+            .collect();
+        f(self);
+
+        let mut changes = ChangeSet::default();
+        for (id, before) in ids.iter().zip(before) {
+            match (before, self.instances.get(id)) {
+                (None, Some(_)) => changes.created.push(id.to_string()),
+                (Some(_), None) => changes.deleted.push(id.to_string()),
+                (Some(before), Some(after)) if before != *after => changes.updated.push(Updated {
+                    id: id.to_string(),
+                    matrix: before.matrix != after.matrix,
+                    config: before.config != after.config,
+                    component: before.component_label != after.component_label,
+                }),
+                _ => {}
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{
+            add_extrude_instance, DesignOperation, ExtrudeAddLength, MoveInstance,
+        },
+    };
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn change_set_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+
+        let op = add_extrude_instance(&mut design, component, 100000).unwrap();
+        let id = op.instance_ids()[0];
+        let changes = design.push(op).unwrap();
+        assert_eq!(changes.created, vec![id.to_string()]);
+
+        let changes = design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: 1000,
+                new_matrix: Isometry3::identity(),
+                old_matrix: None,
+            }))
+            .unwrap();
+        assert_eq!(
+            changes.updated,
+            vec![Updated {
+                id: id.to_string(),
+                matrix: false,
+                config: true,
+                component: false,
+            }]
+        );
+
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id,
+                new_matrix: Isometry3::translation(1.0, 0.0, 0.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        let changes = design.pop();
+        assert!(changes.updated[0].matrix && !changes.updated[0].config);
+        assert!(changes.created.is_empty() && changes.deleted.is_empty());
+
+        let changes = design.jump_to(0).unwrap();
+        assert_eq!(changes.deleted, vec![id.to_string()]);
+        let changes = design.repush();
+        assert_eq!(changes.created, vec![id.to_string()]);
+        // nothing left to redo changes nothing
+        design.jump_to(3).unwrap();
+        assert!(design.repush().is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    component::{Component, ComponentType},
+    instance::{Instance, InstanceConfig},
+    unit::{placement, snap_position},
+    Quaternion, Translation,
+};
+
+use super::{
+    id::parse_ids,
+    joint::Joint,
+    operation::{AddInstance, AddJoint, Batch, DesignOperation},
+    DesignSpace,
+};
+
+// 剪贴板中的一个实例, 变换相对于选中实例的中心
+#[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
+pub struct ClipboardItem {
+    pub component_label: String,
+    pub component_type: ComponentType,
+    pub translation: Translation, // m
+    pub rotation: Quaternion,
+    pub config: InstanceConfig,
+}
+
+// 可序列化为 JSON, 在其他 DesignSpace 或浏览器标签页中粘贴
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Clipboard {
+    pub items: Vec<ClipboardItem>,
+    pub joints: Vec<(usize, usize)>, // items 中的序号
+    pub components: Vec<Component>,
+}
+
+impl DesignSpace {
+    /// the instances `ids` relative to their centre, with the joints among them and
+    /// the components they use
+    pub(crate) fn copy_to_clipboard(&self, ids: &[String]) -> Result<Clipboard, String> {
+        let instances = parse_ids(ids)?
+            .iter()
+            .map(|id| {
+                self.instances
+                    .get(id)
+                    .ok_or_else(|| format!("instance not found: {}", id))
+            })
+            .collect::<Result<Vec<&Instance>, String>>()?;
+        if instances.is_empty() {
+            return Err("nothing to copy".to_string());
+        }
+        // 中心落在网格上, 相对位移也就在网格上
+        let center = (instances
+            .iter()
+            .map(|i| i.matrix.translation.vector)
+            .sum::<Vector3<f64>>()
+            / instances.len() as f64)
+            .map(snap_position);
+
+        let mut clipboard = Clipboard {
+            items: Vec::new(),
+            joints: Vec::new(),
+            components: Vec::new(),
+        };
+        let mut index = HashMap::new();
+        for (n, instance) in instances.iter().enumerate() {
+            index.insert(instance.id, n);
+            let t = instance.matrix.translation.vector - center;
+            let r = instance.matrix.rotation;
+            clipboard.items.push(ClipboardItem {
+                component_label: instance.component_label.clone(),
+                component_type: instance.component_type,
+                translation: Translation {
+                    x: t.x as f32,
+                    y: t.y as f32,
+                    z: t.z as f32,
+                },
+                rotation: Quaternion {
+                    i: r.i as f32,
+                    j: r.j as f32,
+                    k: r.k as f32,
+                    w: r.w as f32,
+                },
+                config: instance.config.clone(),
+            });
+            if let Some(component) = self.components.components.get(&instance.component_label) {
+                if !clipboard
+                    .components
+                    .iter()
+                    .any(|c| c.label == component.label)
+                {
+                    clipboard.components.push(component.clone());
+                }
+            }
+        }
+        let mut joints: Vec<&Joint> = self.joints.values().collect();
+        joints.sort_by_key(|j| j.id);
+        for joint in joints {
+            if let (Some(a), Some(b)) = (index.get(&joint.a), index.get(&joint.b)) {
+                clipboard.joints.push((*a, *b));
+            }
+        }
+        Ok(clipboard)
+    }
+}
+
+/// add the copied instances with fresh ids, their centre placed at `tra` and turned by `quat`.
+/// Register `clipboard.components` with `add_component` first, registering is not undoable
+/// so it is not part of the paste.
+#[wasm_bindgen]
+pub fn paste_clipboard(
+    design: &mut DesignSpace,
+    clipboard: Clipboard,
+    tra: Translation,
+    quat: Quaternion,
+) -> Result<DesignOperation, String> {
+    if clipboard
+        .joints
+        .iter()
+        .any(|(a, b)| *a >= clipboard.items.len() || *b >= clipboard.items.len())
+    {
+        return Err("invalid clipboard joint".to_string());
+    }
+    if let Some(item) = clipboard.items.iter().find(|i| {
+        !design
+            .components
+            .components
+            .contains_key(&i.component_label)
+    }) {
+        return Err(format!("component not found: {}", item.component_label));
+    }
+
+    let target = placement(&tra, &quat);
+    let mut operations = Vec::new();
+    let mut ids = Vec::new();
+    for item in clipboard.items {
+        let mut instance = Instance {
+            id: design.ids.next_id(),
+            component_label: item.component_label,
+            component_type: item.component_type,
+            matrix: target * placement(&item.translation, &item.rotation),
+            config: item.config,
+        };
+        instance.snap_position();
+        ids.push(instance.id);
+        operations.push(DesignOperation::AddInstance(AddInstance { instance }));
+    }
+    for (a, b) in clipboard.joints {
+        operations.push(DesignOperation::AddJoint(AddJoint {
+            joint: Joint::new(design.ids.next_id(), ids[a], ids[b]),
+        }));
+    }
+    Ok(DesignOperation::Batch(Batch { operations }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{add_extrude_instance, add_joint},
+    };
+    use nalgebra::{Isometry3, UnitQuaternion};
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn clipboard_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut a = DesignSpace::new();
+        a.add_component(bar);
+        let mut ids = Vec::new();
+        for x in [1.0, 2.0] {
+            let mut op = add_extrude_instance(&mut a, bar, 100000).unwrap();
+            if let DesignOperation::AddInstance(op) = &mut op {
+                op.instance.matrix = Isometry3::translation(x, 1.0, 0.0);
+            }
+            ids.push(op.instance_ids()[0]);
+            a.push(op).unwrap();
+        }
+        let (x, y) = (a.instances[&ids[0]].clone(), a.instances[&ids[1]].clone());
+        let op = add_joint(&mut a, &x, &y);
+        a.push(op).unwrap();
+
+        let clipboard = a
+            .copy(ids.iter().map(|id| id.to_string()).collect())
+            .unwrap();
+        assert_eq!(clipboard.items[0].translation.x, -0.5);
+        assert_eq!(clipboard.joints, vec![(0, 1)]);
+
+        // paste into another tab through json, turned a quarter around z
+        let json = serde_json::to_string(&clipboard).unwrap();
+        let clipboard: Clipboard = serde_json::from_str(&json).unwrap();
+        let mut b = DesignSpace::new();
+        let quat = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f32::consts::FRAC_PI_2);
+        let paste = |b: &mut DesignSpace| {
+            paste_clipboard(
+                b,
+                clipboard.clone(),
+                Translation {
+                    x: 0.,
+                    y: 0.,
+                    z: 1.,
+                },
+                Quaternion {
+                    i: quat.i,
+                    j: quat.j,
+                    k: quat.k,
+                    w: quat.w,
+                },
+            )
+        };
+        // the components have to be registered before pasting
+        assert!(paste(&mut b).is_err());
+        for component in clipboard.components.iter() {
+            b.add_component(component);
+        }
+        let op = paste(&mut b).unwrap();
+        let pasted = op.instance_ids();
+        b.push(op).unwrap();
+        assert_eq!(b.instances.len(), 2);
+        assert_eq!(b.joints.len(), 1);
+        assert!(!pasted.contains(&ids[0]) && !pasted.contains(&ids[1]));
+        let p = b.instances[&pasted[1]].matrix.translation.vector;
+        assert!((p - Vector3::new(0.0, 0.5, 1.0)).norm() < 1e-6);
+        assert!(b
+            .joints
+            .values()
+            .next()
+            .unwrap()
+            .connects(&pasted[0], &pasted[1]));
+
+        // one undo removes the paste
+        b.pop();
+        assert!(b.instances.is_empty() && b.joints.is_empty());
+        assert!(b.components.components.contains_key("LCF8-4040"));
+        assert!(a.copy(vec![]).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use crate::{
+    instance::{ExtrudeConfig, Instance, InstanceConfig, PanelConfig},
+    Quaternion, Translation,
+};
+
+use super::DesignSpace;
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq)]
+pub struct Moved {
+    pub id: String,
+    pub translation: Translation, // 位移 m
+    pub rotation: Quaternion,     // 相对旋转
+}
+
+// 长度或面板尺寸的变化 0.01mm
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Resized {
+    pub id: String,
+    pub field: String,
+    pub before: u32,
+    pub after: u32,
+}
+
+// 后处理等其他配置的变化
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Reconfigured {
+    pub id: String,
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+// 相同零件与配置的数量变化
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BomChange {
+    pub component: String,
+    pub config: InstanceConfig,
+    pub before: u32,
+    pub after: u32,
+}
+
+#[derive(Debug, Clone, Default, Tsify, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DesignDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub moved: Vec<Moved>,
+    pub resized: Vec<Resized>,
+    pub reconfigured: Vec<Reconfigured>,
+    pub bom: Vec<BomChange>,
+}
+
+impl DesignDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == DesignDiff::default()
+    }
+
+    fn resize(&mut self, id: &str, field: &str, before: u32, after: u32) {
+        if before != after {
+            self.resized.push(Resized {
+                id: id.to_string(),
+                field: field.to_string(),
+                before,
+                after,
+            });
+        }
+    }
+
+    fn reconfigure<T: PartialEq + std::fmt::Debug>(
+        &mut self,
+        id: &str,
+        field: &str,
+        before: &T,
+        after: &T,
+    ) {
+        if before != after {
+            self.reconfigured.push(Reconfigured {
+                id: id.to_string(),
+                field: field.to_string(),
+                before: format!("{:?}", before),
+                after: format!("{:?}", after),
+            });
+        }
+    }
+
+    fn compare(&mut self, before: &Instance, after: &Instance) {
+        let id = before.id.to_string();
+        if before.matrix != after.matrix {
+            let delta = after.matrix.translation.vector - before.matrix.translation.vector;
+            let rotation = after.matrix.rotation * before.matrix.rotation.inverse();
+            self.moved.push(Moved {
+                id: id.clone(),
+                translation: Translation {
+                    x: delta.x as f32,
+                    y: delta.y as f32,
+                    z: delta.z as f32,
+                },
+                rotation: Quaternion {
+                    i: rotation.i as f32,
+                    j: rotation.j as f32,
+                    k: rotation.k as f32,
+                    w: rotation.w as f32,
+                },
+            });
+        }
+        self.reconfigure(
+            &id,
+            "component",
+            &before.component_label,
+            &after.component_label,
+        );
+        match (&before.config, &after.config) {
+            (InstanceConfig::Extrude(b), InstanceConfig::Extrude(a)) => {
+                let ExtrudeConfig {
+                    drill_left,
+                    drill_right,
+                    bevel_cut,
+                    wrench_hole_left,
+                    wrench_hole_right,
+                    counterbore_left,
+                    counterbore_right,
+                    length,
+                } = b;
+                self.resize(&id, "length", *length, a.length);
+                self.reconfigure(&id, "drill_left", drill_left, &a.drill_left);
+                self.reconfigure(&id, "drill_right", drill_right, &a.drill_right);
+                self.reconfigure(&id, "bevel_cut", bevel_cut, &a.bevel_cut);
+                self.reconfigure(
+                    &id,
+                    "wrench_hole_left",
+                    wrench_hole_left,
+                    &a.wrench_hole_left,
+                );
+                self.reconfigure(
+                    &id,
+                    "wrench_hole_right",
+                    wrench_hole_right,
+                    &a.wrench_hole_right,
+                );
+                self.reconfigure(
+                    &id,
+                    "counterbore_left",
+                    counterbore_left,
+                    &a.counterbore_left,
+                );
+                self.reconfigure(
+                    &id,
+                    "counterbore_right",
+                    counterbore_right,
+                    &a.counterbore_right,
+                );
+            }
+            (InstanceConfig::Panel(b), InstanceConfig::Panel(a)) => {
+                let PanelConfig { x, y, thickness } = b;
+                self.resize(&id, "x", *x, a.x);
+                self.resize(&id, "y", *y, a.y);
+                self.resize(&id, "thickness", *thickness, a.thickness);
+            }
+            (b, a) => self.reconfigure(&id, "config", b, a),
+        }
+    }
+}
+
+/// quantity of each component and config, instances with the same config are the same part
+fn bom(space: &DesignSpace) -> Vec<((String, InstanceConfig), u32)> {
+    let mut lines: Vec<((String, InstanceConfig), u32)> = Vec::new();
+    let mut instances: Vec<&Instance> = space.instances.values().collect();
+    instances.sort_by_key(|i| i.id);
+    for i in instances {
+        let key = (i.component_label.clone(), i.config.clone());
+        match lines.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, count)) => *count += 1,
+            None => lines.push((key, 1)),
+        }
+    }
+    lines
+}
+
+impl DesignSpace {
+    /// changes from `self` to `other`, instances are matched by id
+    pub(crate) fn diff_to(&self, other: &DesignSpace) -> DesignDiff {
+        let mut diff = DesignDiff::default();
+        let mut ids: Vec<_> = self
+            .instances
+            .keys()
+            .chain(other.instances.keys())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        for id in ids {
+            match (self.instances.get(id), other.instances.get(id)) {
+                (Some(before), Some(after)) => diff.compare(before, after),
+                (Some(_), None) => diff.removed.push(id.to_string()),
+                (None, Some(_)) => diff.added.push(id.to_string()),
+                (None, None) => {}
+            }
+        }
+
+        let before = bom(self);
+        let after = bom(other);
+        let count = |lines: &[((String, InstanceConfig), u32)], key: &(String, InstanceConfig)| {
+            lines
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(0, |(_, count)| *count)
+        };
+        let mut keys: Vec<&(String, InstanceConfig)> =
+            before.iter().chain(after.iter()).map(|(k, _)| k).collect();
+        keys.sort_by_key(|(label, config)| (label.clone(), format!("{:?}", config)));
+        keys.dedup();
+        for key in keys {
+            let (b, a) = (count(&before, key), count(&after, key));
+            if b != a {
+                diff.bom.push(BomChange {
+                    component: key.0.clone(),
+                    config: key.1.clone(),
+                    before: b,
+                    after: a,
+                });
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{
+            add_extrude_instance, DesignOperation, ExtrudeAddLength, MoveInstance,
+            PostProcessInstance, RemoveInstance,
+        },
+    };
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn diff_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let mut before = DesignSpace::new();
+        before.use_sequential_ids(1);
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let op = add_extrude_instance(&mut before, component, 100000).unwrap();
+            ids.extend(op.instance_ids());
+            before.push(op).unwrap();
+        }
+        let mut after = DesignSpace::replay(before.export_log()).unwrap();
+        assert!(before.diff_to(&after).is_empty());
+
+        after
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id: ids[0],
+                new_matrix: Isometry3::translation(0.1, 0.0, 0.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        after
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id: ids[1],
+                dlength: 10000,
+                new_matrix: Isometry3::identity(),
+                old_matrix: None,
+            }))
+            .unwrap();
+        let mut config = match InstanceConfig::default_extrude(0) {
+            InstanceConfig::Extrude(e) => e,
+            _ => unreachable!(),
+        };
+        config.drill_left = true;
+        after
+            .push(DesignOperation::PostProcessInstance(PostProcessInstance {
+                id: ids[1],
+                config: InstanceConfig::Extrude(config.clone()),
+                config_cache: None,
+            }))
+            .unwrap();
+        after
+            .push(DesignOperation::RemoveInstance(RemoveInstance {
+                id: ids[2],
+                removed_instance: None,
+                removed_joints: Vec::new(),
+            }))
+            .unwrap();
+        let op = add_extrude_instance(&mut after, component, 100000).unwrap();
+        let added = op.instance_ids()[0];
+        after.push(op).unwrap();
+
+        let diff = before.diff_to(&after);
+        assert_eq!(diff.added, vec![added.to_string()]);
+        assert_eq!(diff.removed, vec![ids[2].to_string()]);
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].id, ids[0].to_string());
+        assert!((diff.moved[0].translation.x - 0.1).abs() < 1e-6);
+        assert_eq!(diff.moved[0].rotation, Quaternion::identity());
+        assert_eq!(
+            diff.resized,
+            vec![Resized {
+                id: ids[1].to_string(),
+                field: "length".to_string(),
+                before: 100000,
+                after: 110000,
+            }]
+        );
+        assert_eq!(
+            diff.reconfigured,
+            vec![Reconfigured {
+                id: ids[1].to_string(),
+                field: "drill_left".to_string(),
+                before: "false".to_string(),
+                after: "true".to_string(),
+            }]
+        );
+
+        // 3 plain 1m bars become 2 plain and 1 drilled 1.1m bar
+        config.length = 110000;
+        assert_eq!(
+            diff.bom,
+            vec![
+                BomChange {
+                    component: "LCF8-4040".to_string(),
+                    config: InstanceConfig::default_extrude(100000),
+                    before: 3,
+                    after: 2,
+                },
+                BomChange {
+                    component: "LCF8-4040".to_string(),
+                    config: InstanceConfig::Extrude(config),
+                    before: 0,
+                    after: 1,
+                },
+            ]
+        );
+    }
+}
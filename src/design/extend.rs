@@ -0,0 +1,253 @@
+use nalgebra::{Point3, Vector3};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    component::ComponentData,
+    instance::{Instance, InstanceConfig, LENGTH_UNIT},
+};
+
+use super::{
+    joint::Joint,
+    operation::{extrude_set_length, AddJoint, Batch, DesignOperation, ExtrudeAnchor},
+    DesignSpace,
+};
+
+/// extend or trim the end of an extrusion nearer to `target` so it sits flush on a face of
+/// `target`. The face follows the extrusion's own end cut, connectors are not considered: a
+/// square cut end butts on the near face, a bevel cut end mitres to the far face. The length
+/// snaps down to the component's step, so the end never cuts into `target`, and a target out
+/// of the stock length range is refused. The end then touches `target` on purpose, so a
+/// joint is added too.
+#[wasm_bindgen]
+pub fn extrude_extend_to(
+    design: &mut DesignSpace,
+    instance: &Instance,
+    target: &Instance,
+) -> Result<DesignOperation, String> {
+    let (length, mitred) = match &instance.config {
+        InstanceConfig::Extrude(e) => (e.length as f32 / LENGTH_UNIT, e.bevel_cut.is_some()),
+        _ => return Err("invalid component type: Not Extrude".to_string()),
+    };
+    let component_of = |i: &Instance| {
+        design
+            .components
+            .components
+            .get(&i.component_label)
+            .ok_or_else(|| format!("component not found: {}", i.component_label))
+    };
+    let component = component_of(instance)?;
+    let range = match &component.data {
+        ComponentData::Extrude(data) => &data.post_process.length,
+        _ => return Err("invalid component type: Not Extrude".to_string()),
+    };
+    let target_box = target
+        .bounding_box(component_of(target)?)
+        .ok_or_else(|| format!("unknown size of instance: {}", target.id))?;
+
+    // 靠近目标的一端移动, 另一端不动
+    let matrix = instance.isometry();
+    let axis = matrix.rotation * Vector3::x();
+    let center: Point3<f32> = matrix.translation.vector.into();
+    let (anchor, dir) = if (target_box.center() - center).dot(&axis) >= 0. {
+        (ExtrudeAnchor::Left, axis)
+    } else {
+        (ExtrudeAnchor::Right, -axis)
+    };
+    let fixed_end = center - dir * (length / 2.);
+    // 直角端面抵住近面, 斜切端面 (45° 角接) 的外缘到达远面
+    let required = match target_box.ray_interval(&fixed_end, &dir) {
+        Some((near, _)) if !mitred && near > 0. => near,
+        Some((_, far)) if mitred && far > 0. => far,
+        _ => return Err("target is not in front of the extrusion end".to_string()),
+    };
+    let required = (required * LENGTH_UNIT).round() as u32;
+    let new_length = range
+        .snap_down(required)
+        .ok_or_else(|| format!("no stock length reaches the target: {}", required))?;
+
+    let op = extrude_set_length(instance, component, new_length, anchor)?;
+    // 只有端面贴合目标 (误差小于一个步长) 时才加连接
+    let flush = required - new_length <= range.step.max(1);
+    if !flush
+        || design
+            .joints
+            .values()
+            .any(|j| j.connects(&instance.id, &target.id))
+    {
+        return Ok(op);
+    }
+    Ok(DesignOperation::Batch(Batch {
+        operations: vec![
+            op,
+            DesignOperation::AddJoint(AddJoint {
+                joint: Joint::new(design.ids.next_id(), instance.id, target.id),
+            }),
+        ],
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{add_extrude_instance, MoveInstance, PostProcessInstance},
+        instance::BevelCutConfig,
+    };
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn extrude_length(instance: &Instance) -> u32 {
+        match &instance.config {
+            InstanceConfig::Extrude(e) => e.length,
+            _ => unreachable!(),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn extend_to_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        design.add_component(bar);
+        let mut ids = Vec::new();
+        // the target crosses along z
+        let crossing = |x: f64| {
+            Isometry3::new(
+                Vector3::new(x, 0.0, 0.0),
+                Vector3::y() * std::f64::consts::FRAC_PI_2,
+            )
+        };
+        for matrix in [Isometry3::identity(), crossing(0.8)] {
+            let mut op = add_extrude_instance(&mut design, bar, 100000).unwrap();
+            if let DesignOperation::AddInstance(op) = &mut op {
+                op.instance.matrix = matrix;
+            }
+            ids.extend(op.instance_ids());
+            design.push(op).unwrap();
+        }
+        let end = |design: &DesignSpace| {
+            let i = &design.instances[&ids[0]];
+            let half = extrude_length(i) as f32 / LENGTH_UNIT / 2.;
+            (
+                i.isometry() * Point3::new(-half, 0.0, 0.0),
+                i.isometry() * Point3::new(half, 0.0, 0.0),
+            )
+        };
+        let (left, _) = end(&design);
+
+        // extend to the near face of the target at x = 0.78
+        let (instance, target) = (
+            design.instances[&ids[0]].clone(),
+            design.instances[&ids[1]].clone(),
+        );
+        let op = extrude_extend_to(&mut design, &instance, &target).unwrap();
+        design.push(op).unwrap();
+        assert_eq!(extrude_length(&design.instances[&ids[0]]), 128000);
+        let (l, r) = end(&design);
+        assert!((l - left).norm() < 1e-5);
+        assert!((r.x - 0.78).abs() < 1e-5);
+        assert_eq!(design.joints.len(), 1);
+        assert_eq!(design.history().labels.len(), 3);
+
+        // trim back after the target moves closer, the joint is kept
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id: ids[1],
+                new_matrix: crossing(0.3),
+                old_matrix: None,
+            }))
+            .unwrap();
+        let (instance, target) = (
+            design.instances[&ids[0]].clone(),
+            design.instances[&ids[1]].clone(),
+        );
+        let op = extrude_extend_to(&mut design, &instance, &target).unwrap();
+        design.push(op).unwrap();
+        assert_eq!(extrude_length(&design.instances[&ids[0]]), 78000);
+        assert_eq!(design.joints.len(), 1);
+
+        // undoing the extension restores the member and removes the joint
+        design.pop();
+        design.pop();
+        assert_eq!(design.joints.len(), 1);
+        design.pop();
+        assert_eq!(extrude_length(&design.instances[&ids[0]]), 100000);
+        assert!(design.joints.is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn extend_to_face_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        design.add_component(bar);
+        let mut ids = Vec::new();
+        // the near face of the target at x = 0.7813 is off the 0.5mm length step
+        let crossing = Isometry3::new(
+            Vector3::new(0.8013, 0.0, 0.0),
+            Vector3::y() * std::f64::consts::FRAC_PI_2,
+        );
+        for matrix in [Isometry3::identity(), crossing] {
+            let mut op = add_extrude_instance(&mut design, bar, 100000).unwrap();
+            if let DesignOperation::AddInstance(op) = &mut op {
+                op.instance.matrix = matrix;
+            }
+            ids.extend(op.instance_ids());
+            design.push(op).unwrap();
+        }
+        let extend = |design: &mut DesignSpace| {
+            let (instance, target) = (
+                design.instances[&ids[0]].clone(),
+                design.instances[&ids[1]].clone(),
+            );
+            let op = extrude_extend_to(design, &instance, &target).unwrap();
+            design.push(op).unwrap();
+            extrude_length(&design.instances[&ids[0]])
+        };
+
+        // a square end stops short of the near face instead of cutting into it
+        assert_eq!(extend(&mut design), 128100);
+
+        // a bevel cut end reaches the far face at x = 0.8213
+        let InstanceConfig::Extrude(mut config) = design.instances[&ids[0]].config.clone() else {
+            unreachable!()
+        };
+        config.bevel_cut = Some(BevelCutConfig::TopToBottom);
+        design
+            .push(DesignOperation::PostProcessInstance(PostProcessInstance {
+                id: ids[0],
+                config: InstanceConfig::Extrude(config),
+                config_cache: None,
+            }))
+            .unwrap();
+        assert_eq!(extend(&mut design), 132100);
+    }
+
+    #[wasm_bindgen_test]
+    fn extend_beyond_stock_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        design.add_component(bar);
+        let mut instances = Vec::new();
+        // the target is 4.05m from the fixed end, past the 4m max stock length
+        let crossing = Isometry3::new(
+            Vector3::new(4.02, 0.0, 0.0),
+            Vector3::y() * std::f64::consts::FRAC_PI_2,
+        );
+        for matrix in [Isometry3::identity(), crossing] {
+            let mut op = add_extrude_instance(&mut design, bar, 100000).unwrap();
+            if let DesignOperation::AddInstance(op) = &mut op {
+                op.instance.matrix = matrix;
+            }
+            let id = op.instance_ids()[0];
+            design.push(op).unwrap();
+            instances.push(design.instances[&id].clone());
+        }
+        assert!(extrude_extend_to(&mut design, &instances[0], &instances[1]).is_err());
+        assert_eq!(extrude_length(&design.instances[&instances[0].id]), 100000);
+        assert!(design.joints.is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use nalgebra::{Point3, Vector3};
+use uuid::Uuid;
+
+use crate::{
+    component::{BoltRecommendation, ComponentData},
+    instance::{Instance, LENGTH_UNIT},
+};
+
+use super::DesignSpace;
+
+// 螺栓连接的长度问题, 螺栓原点在螺栓头下端面, 沿局部 +x 拧入
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq)]
+pub struct BoltWarning {
+    pub joint: String,                      // 螺栓与螺母的连接
+    pub bolt: String,                       // 螺栓实例
+    pub bolt_length: u32,                   // 当前螺栓长度 0.01mm
+    pub recommendation: BoltRecommendation, // 按叠层推荐的长度范围与螺栓
+}
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct BoltWarnings(pub Vec<BoltWarning>);
+
+impl DesignSpace {
+    fn component_data(&self, instance: &Instance) -> Option<&ComponentData> {
+        self.components
+            .components
+            .get(&instance.component_label)
+            .map(|c| &c.data)
+    }
+
+    /// the clamp stack of a bolt jointed to a slot nut, from the bolt head to the profile
+    /// holding the nut: (nut, profile, clamp thickness 0.01mm)
+    fn clamp_stack(&self, bolt: &Instance, nut: &Instance) -> Option<(String, String, u32)> {
+        if !self.component_data(nut)?.is_slot_nut() {
+            return None;
+        }
+        let matrix = bolt.isometry();
+        let origin = Point3::from(matrix.translation.vector);
+        let axis = matrix.rotation * Vector3::x();
+        self.joints
+            .values()
+            .filter_map(|j| match (j.a == nut.id, j.b == nut.id) {
+                (true, _) => self.instances.get(&j.b),
+                (_, true) => self.instances.get(&j.a),
+                _ => None,
+            })
+            .filter(|profile| profile.id != bolt.id)
+            .find_map(|profile| {
+                let component = self.components.components.get(&profile.component_label)?;
+                let distance = profile
+                    .bounding_box(component)?
+                    .ray_distance(&origin, &axis)?;
+                Some((
+                    nut.component_label.clone(),
+                    profile.component_label.clone(),
+                    (distance * LENGTH_UNIT).round() as u32,
+                ))
+            })
+    }
+
+    /// bolted joints whose bolt does not fit the clamp stack, or for which no catalog bolt fits
+    pub(crate) fn find_bolt_warnings(&self) -> Vec<BoltWarning> {
+        let mut joints: Vec<(&Uuid, Uuid, Uuid)> =
+            self.joints.values().map(|j| (&j.id, j.a, j.b)).collect();
+        joints.sort();
+
+        let mut warnings = Vec::new();
+        for (joint, a, b) in joints {
+            for (bolt, nut) in [(a, b), (b, a)] {
+                let (Some(bolt), Some(nut)) = (self.instances.get(&bolt), self.instances.get(&nut))
+                else {
+                    continue;
+                };
+                let Some(bolt_length) = self.component_data(bolt).and_then(|d| d.bolt_length())
+                else {
+                    continue;
+                };
+                let Some((nut, profile, clamp_thickness)) = self.clamp_stack(bolt, nut) else {
+                    continue;
+                };
+                let Ok(recommendation) =
+                    self.components
+                        .recommend_bolt_for(&profile, &nut, clamp_thickness)
+                else {
+                    continue;
+                };
+                let (min, max) = (recommendation.min_length, recommendation.max_length);
+                if !recommendation.is_valid() || !(min..=max).contains(&bolt_length) {
+                    warnings.push(BoltWarning {
+                        joint: joint.to_string(),
+                        bolt: bolt.id.to_string(),
+                        bolt_length,
+                        recommendation,
+                    });
+                }
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::test_util::bolt_lib,
+        design::operation::{
+            add_joint, replace_component, AddInstance, DesignOperation, MoveInstance,
+        },
+    };
+    use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn bolt_warning_test() {
+        let lib = bolt_lib();
+        let mut design = DesignSpace::new();
+        for label in ["LCF8-4040", "Nut-SW8-M8", "Bolt-M8-12", "Bolt-M8-30"] {
+            design.add_component(&lib.components[label]);
+        }
+        let add = |design: &mut DesignSpace, op: AddInstance| {
+            let instance = op.instance.clone();
+            design.push(DesignOperation::AddInstance(op)).unwrap();
+            instance
+        };
+        let profile =
+            AddInstance::extrude(&mut design.ids, &lib.components["LCF8-4040"], 100000).unwrap();
+        let profile = add(&mut design, profile);
+        let nut = AddInstance::default_component(&mut design.ids, &lib.components["Nut-SW8-M8"]);
+        let nut = add(&mut design, nut);
+        let bolt = AddInstance::default_component(&mut design.ids, &lib.components["Bolt-M8-30"]);
+        let bolt = add(&mut design, bolt);
+        let op = add_joint(&mut design, &profile, &nut);
+        design.push(op).unwrap();
+        let op = add_joint(&mut design, &bolt, &nut);
+        design.push(op).unwrap();
+
+        // the bolt head sits on a 5mm bracket on top of the profile, screwing down
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id: bolt.id,
+                new_matrix: Isometry3::from_parts(
+                    Translation3::new(0.0, 0.0, 0.025),
+                    UnitQuaternion::from_axis_angle(
+                        &Vector3::y_axis(),
+                        std::f64::consts::FRAC_PI_2,
+                    ),
+                ),
+                old_matrix: None,
+            }))
+            .unwrap();
+
+        // 30mm bottoms out in the slot, 11..16.8mm fits
+        let warnings = design.bolt_warnings().0;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].bolt, bolt.id.to_string());
+        assert_eq!(warnings[0].bolt_length, 3000);
+        assert_eq!(warnings[0].recommendation.min_length, 1100);
+        assert_eq!(warnings[0].recommendation.max_length, 1680);
+        assert_eq!(
+            warnings[0].recommendation.bolt,
+            Some("Bolt-M8-12".to_string())
+        );
+
+        let op =
+            replace_component(&design.instances[&bolt.id], &lib.components["Bolt-M8-12"]).unwrap();
+        design.push(op).unwrap();
+        assert!(design.bolt_warnings().0.is_empty());
+    }
+}
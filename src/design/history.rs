@@ -0,0 +1,186 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::{
+    instance::Instance,
+    unit::{from_length, LengthUnit},
+};
+
+use super::{operation::DesignOperation, DesignSpace, Record};
+
+// 历史记录面板, labels 依次为已执行与可重做的操作
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct History {
+    pub labels: Vec<String>,
+    pub cursor: usize, // 已执行操作数, labels[cursor..] 可重做
+}
+
+impl DesignOperation {
+    /// human readable description, `instances` resolves the component of edited instances
+    pub(crate) fn label(&self, instances: &HashMap<Uuid, Instance>) -> String {
+        let component = |id: &Uuid| {
+            instances
+                .get(id)
+                .map_or("instance", |i| i.component_label.as_str())
+                .to_string()
+        };
+        match self {
+            DesignOperation::AddInstance(op) => format!("Add {}", op.instance.component_label),
+            DesignOperation::RemoveInstance(op) => format!(
+                "Remove {}",
+                op.removed_instance
+                    .as_ref()
+                    .map_or_else(|| component(&op.id), |i| i.component_label.clone())
+            ),
+            DesignOperation::PostProcessInstance(op) => {
+                format!("Post process {}", component(&op.id))
+            }
+            DesignOperation::ExtrudeAddLength(op) => format!(
+                "{} {} by {}mm",
+                if op.dlength < 0 {
+                    "Shorten"
+                } else {
+                    "Lengthen"
+                },
+                component(&op.id),
+                from_length(op.dlength.unsigned_abs(), LengthUnit::Millimeter)
+            ),
+            DesignOperation::PanelAddSize(op) => format!("Resize {}", component(&op.id)),
+            DesignOperation::MoveInstance(op) => format!("Move {}", component(&op.id)),
+            DesignOperation::SetInstance(op) => {
+                format!("Update {}", op.instance.component_label)
+            }
+            DesignOperation::ReplaceComponent(op) => format!(
+                "Replace {} with {}",
+                op.old_component
+                    .as_ref()
+                    .map_or_else(|| component(&op.id), |(label, ..)| label.clone()),
+                op.component_label
+            ),
+            DesignOperation::SplitExtrude(op) => format!("Split {}", op.left.component_label),
+            DesignOperation::JoinExtrude(op) => format!("Join {}", op.instance.component_label),
+            DesignOperation::Pattern(op) => format!("Pattern {} items", op.feature.sources.len()),
+            DesignOperation::EditPattern(_) => "Edit pattern".to_string(),
+            DesignOperation::SetPattern(_) => "Update pattern".to_string(),
+            DesignOperation::AddJoint(_) => "Add joint".to_string(),
+            DesignOperation::RemoveJoint(_) => "Remove joint".to_string(),
+            DesignOperation::Batch(op) => match op.operations.as_slice() {
+                [op] => op.label(instances),
+                operations => format!("Edit {} items", operations.len()),
+            },
+        }
+    }
+}
+
+impl DesignSpace {
+    pub(crate) fn history_labels(&self) -> History {
+        History {
+            labels: self
+                .records
+                .iter()
+                .chain(self.poped.iter().rev())
+                .map(|op| op.label(&self.instances))
+                .collect(),
+            cursor: self.records.len(),
+        }
+    }
+
+    /// undo or redo until `cursor` operations are applied
+    pub(crate) fn jump_history(&mut self, cursor: usize) -> Result<(), String> {
+        self.commit_transaction();
+        if cursor > self.records.len() + self.poped.len() {
+            return Err(format!("invalid history index: {}", cursor));
+        }
+        while self.records.len() > cursor {
+            Record::pop(self);
+        }
+        while self.records.len() < cursor {
+            Record::repush(self);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{AddInstance, ExtrudeAddLength, MoveInstance},
+        instance::InstanceConfig,
+    };
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn history_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+
+        let add = AddInstance::extrude(
+            &mut design.ids,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let id = add.instance.id;
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: 10000,
+                new_matrix: Isometry3::identity(),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id,
+                new_matrix: Isometry3::translation(1.0, 0.0, 0.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design.pop();
+
+        assert_eq!(
+            design.history_labels(),
+            History {
+                labels: vec![
+                    "Add LCF8-4040".to_string(),
+                    "Lengthen LCF8-4040 by 100mm".to_string(),
+                    "Move LCF8-4040".to_string(),
+                ],
+                cursor: 2,
+            }
+        );
+
+        design.jump_history(0).unwrap();
+        assert!(design.instances.is_empty());
+        assert_eq!(design.history_labels().cursor, 0);
+        assert_eq!(design.history_labels().labels.len(), 3);
+
+        design.jump_history(3).unwrap();
+        assert_eq!(
+            design.instances[&id].matrix,
+            Isometry3::translation(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            design.instances[&id].config,
+            InstanceConfig::default_extrude(110000)
+        );
+
+        design.jump_history(1).unwrap();
+        assert_eq!(
+            design.instances[&id].config,
+            InstanceConfig::default_extrude(100000)
+        );
+        assert!(design.jump_history(4).is_err());
+    }
+}
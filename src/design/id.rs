@@ -0,0 +1,72 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+use uuid::Uuid;
+
+/// source of new instance ids
+#[derive(Debug, Clone, Default, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+pub enum IdGenerator {
+    #[default]
+    Random,
+    // seed 为高 64 位, 序号为低 64 位, 用于测试与回放
+    Sequential {
+        seed: u64,
+        next: u64,
+    },
+}
+
+impl IdGenerator {
+    pub(crate) fn sequential(seed: u64) -> Self {
+        IdGenerator::Sequential { seed, next: 0 }
+    }
+
+    pub(crate) fn next_id(&mut self) -> Uuid {
+        match self {
+            IdGenerator::Random => Uuid::new_v4(),
+            IdGenerator::Sequential { seed, next } => {
+                let id = Uuid::from_u64_pair(*seed, *next);
+                *next += 1;
+                id
+            }
+        }
+    }
+
+    /// skip ids already in use, e.g. instances added by a replayed log
+    pub(crate) fn skip(&mut self, id: &Uuid) {
+        if let IdGenerator::Sequential { seed, next } = self {
+            let (high, low) = id.as_u64_pair();
+            if high == *seed && low >= *next {
+                *next = low + 1;
+            }
+        }
+    }
+}
+
+/// ids passed in from js
+pub(crate) fn parse_ids(ids: &[String]) -> Result<Vec<Uuid>, String> {
+    ids.iter()
+        .map(|id| Uuid::parse_str(id).map_err(|e| e.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn sequential_id_test() {
+        let mut a = IdGenerator::sequential(7);
+        let mut b = IdGenerator::sequential(7);
+        let ids: Vec<Uuid> = (0..3).map(|_| a.next_id()).collect();
+        assert_eq!(ids, (0..3).map(|_| b.next_id()).collect::<Vec<_>>());
+        assert_eq!(ids[2].to_string(), "00000000-0000-0007-0000-000000000002");
+
+        b.skip(&Uuid::from_u64_pair(7, 10));
+        assert_eq!(b.next_id(), Uuid::from_u64_pair(7, 11));
+        b.skip(&Uuid::from_u64_pair(8, 20));
+        assert_eq!(b.next_id(), Uuid::from_u64_pair(7, 12));
+        assert_ne!(IdGenerator::Random.next_id(), IdGenerator::Random.next_id());
+    }
+}
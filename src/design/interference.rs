@@ -0,0 +1,156 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::instance::Obb;
+
+// 有连接的两个零件允许的贴合误差 m, 超过时仍是干涉
+const JOINT_TOLERANCE: f32 = 0.0005;
+
+use super::DesignSpace;
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq)]
+pub struct Interference {
+    pub a: String,
+    pub b: String,
+    pub depth: f32, // 穿透深度 m
+}
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Interferences(pub Vec<Interference>);
+
+impl DesignSpace {
+    /// intersecting instance pairs. Pairs declared by a joint are intended contacts as long
+    /// as they only touch, a jointed pair passing through each other is still reported.
+    pub(crate) fn find_interferences(&self) -> Vec<Interference> {
+        let boxes: HashMap<Uuid, Obb> = self
+            .instances
+            .values()
+            .filter_map(|i| {
+                let component = self.components.components.get(&i.component_label)?;
+                Some((i.id, i.bounding_box(component)?))
+            })
+            .collect();
+        let mut ids: Vec<&Uuid> = boxes.keys().collect();
+        ids.sort();
+        let jointed: HashSet<(Uuid, Uuid)> = self
+            .joints
+            .values()
+            .map(|j| (j.a.min(j.b), j.a.max(j.b)))
+            .collect();
+
+        let mut interferences = Vec::new();
+        for a in ids {
+            let a_box = &boxes[a];
+            for b in self.index.select_box(&a_box.aabb()) {
+                if b <= *a {
+                    continue;
+                }
+                let Some(b_box) = boxes.get(&b) else {
+                    continue;
+                };
+                if let Some(depth) = a_box.penetration(b_box) {
+                    if depth <= JOINT_TOLERANCE && jointed.contains(&(*a, b)) {
+                        continue;
+                    }
+                    interferences.push(Interference {
+                        a: a.to_string(),
+                        b: b.to_string(),
+                        depth,
+                    });
+                }
+            }
+        }
+        interferences
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::add_joint,
+        design::operation::{AddInstance, DesignOperation, MoveInstance},
+    };
+    use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn interference_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        design.add_component(component);
+
+        let a = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let b = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let c = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let (a_instance, b_instance) = (a.instance.clone(), b.instance.clone());
+        let c_id = c.instance.id;
+        design.push(DesignOperation::AddInstance(a)).unwrap();
+        design.push(DesignOperation::AddInstance(b)).unwrap();
+
+        // b crosses a 10mm deep from above, c sits under a
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id: b_instance.id,
+                new_matrix: Isometry3::from_parts(
+                    Translation3::new(0.0, 0.0, 0.03),
+                    UnitQuaternion::from_axis_angle(
+                        &Vector3::z_axis(),
+                        std::f64::consts::FRAC_PI_2,
+                    ),
+                ),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design.push(DesignOperation::AddInstance(c)).unwrap();
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id: c_id,
+                new_matrix: Isometry3::translation(0.0, 0.0, -0.04),
+                old_matrix: None,
+            }))
+            .unwrap();
+
+        let interferences = design.interferences().0;
+        assert_eq!(interferences.len(), 1);
+        let pair = [interferences[0].a.clone(), interferences[0].b.clone()];
+        assert!(pair.contains(&a_instance.id.to_string()));
+        assert!(pair.contains(&b_instance.id.to_string()));
+        assert!((interferences[0].depth - 0.01).abs() < 1e-5);
+
+        // a joint does not excuse members passing through each other
+        let op = add_joint(&mut design, &a_instance, &b_instance);
+        design.push(op).unwrap();
+        assert_eq!(design.interferences().0.len(), 1);
+
+        // but does excuse members seated on each other
+        let seated = |z| {
+            DesignOperation::MoveInstance(MoveInstance {
+                id: b_instance.id,
+                new_matrix: Isometry3::from_parts(
+                    Translation3::new(0.0, 0.0, z),
+                    UnitQuaternion::from_axis_angle(
+                        &Vector3::z_axis(),
+                        std::f64::consts::FRAC_PI_2,
+                    ),
+                ),
+                old_matrix: None,
+            })
+        };
+        design.push(seated(0.0398)).unwrap();
+        assert!(design.interferences().0.is_empty());
+        design.pop();
+        design.pop();
+        design.push(seated(0.0398)).unwrap();
+        assert_eq!(design.interferences().0.len(), 1);
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use super::DesignSpace;
+
+// 连接关系, 两个实例之间有意的接触 (例如角码连接, 端面贴合)
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Joint {
+    pub(crate) id: Uuid,
+    pub(crate) a: Uuid,
+    pub(crate) b: Uuid,
+}
+
+#[wasm_bindgen]
+impl Joint {
+    pub fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    pub fn a(&self) -> String {
+        self.a.to_string()
+    }
+
+    pub fn b(&self) -> String {
+        self.b.to_string()
+    }
+}
+
+impl Joint {
+    pub(crate) fn new(id: Uuid, a: Uuid, b: Uuid) -> Self {
+        Joint { id, a, b }
+    }
+
+    pub(crate) fn connects(&self, a: &Uuid, b: &Uuid) -> bool {
+        (self.a == *a && self.b == *b) || (self.a == *b && self.b == *a)
+    }
+}
+
+impl DesignSpace {
+    /// remove the joints attached to `id`, returned in id order to be restored on undo
+    pub(crate) fn detach_joints(&mut self, id: &Uuid) -> Vec<Joint> {
+        let mut ids: Vec<Uuid> = self
+            .joints
+            .values()
+            .filter(|j| j.a == *id || j.b == *id)
+            .map(|j| j.id)
+            .collect();
+        ids.sort();
+        ids.iter().filter_map(|id| self.joints.remove(id)).collect()
+    }
+
+    /// put back joints removed by detach_joints, replacing any later edit of them
+    pub(crate) fn restore_joints(&mut self, joints: Vec<Joint>) {
+        for joint in joints {
+            self.joints.insert(joint.id, joint);
+        }
+    }
+}
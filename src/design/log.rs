@@ -0,0 +1,296 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use uuid::Uuid;
+
+use crate::{component::Component, instance::Instance};
+
+use super::{
+    id::IdGenerator, joint::Joint, operation::DesignOperation, pattern::PatternFeature,
+    validate::OperationError, DesignSpace, Record,
+};
+
+// 距上一个快照超过该条数时追加快照
+const SNAPSHOT_INTERVAL: usize = 100;
+
+// 某一时刻的模型状态, 不含撤销历史, 回放时从最近的快照开始
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub instances: Vec<Instance>,
+    pub joints: Vec<Joint>,
+    pub components: Vec<Component>,
+    pub ids: IdGenerator,
+    pub patterns: Vec<PatternFeature>,
+    pub site: u32,
+}
+
+// 对 DesignSpace 的一次调用, Push 保存执行前的操作.
+// Pop 与 Repush 保存被撤销或重做的记录, 快照之前的历史不在日志中时据此回放
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+pub enum LogEntry {
+    AddComponent(Component),
+    Push(DesignOperation),
+    Pop(Option<DesignOperation>),
+    Repush(Option<DesignOperation>),
+    BeginGesture,
+    EndGesture,
+    BeginTransaction,
+    CommitTransaction,
+    RollbackTransaction,
+    SequentialIds(u64),
+    SetSite(u32),
+    Snapshot(Snapshot),
+}
+
+// 只追加的操作日志
+#[derive(Debug, Clone, Default, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct OperationLog {
+    pub entries: Vec<LogEntry>,
+}
+
+impl OperationLog {
+    fn last_snapshot(&self) -> Option<usize> {
+        self.entries
+            .iter()
+            .rposition(|e| matches!(e, LogEntry::Snapshot(_)))
+    }
+}
+
+impl DesignSpace {
+    pub(crate) fn append_log(&mut self, entry: LogEntry) {
+        self.log.entries.push(entry);
+        if self.replaying {
+            // snapshots come from the replayed log
+            return;
+        }
+        // an open transaction or gesture refers to history the snapshot does not keep
+        if self.transaction.is_some() || self.gesture.is_some() {
+            return;
+        }
+        let since = self.log.last_snapshot().map_or(0, |i| i + 1);
+        if self.log.entries.len() - since >= SNAPSHOT_INTERVAL {
+            let snapshot = self.snapshot();
+            self.log.entries.push(LogEntry::Snapshot(snapshot));
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let mut instances: Vec<Instance> = self.instances.values().cloned().collect();
+        instances.sort_by_key(|i| i.id);
+        let mut joints: Vec<Joint> = self.joints.values().cloned().collect();
+        joints.sort_by_key(|j| j.id);
+        let mut components: Vec<Component> = self.components.components.values().cloned().collect();
+        components.sort_by(|a, b| a.label.cmp(&b.label));
+        Snapshot {
+            instances,
+            joints,
+            components,
+            ids: self.ids.clone(),
+            patterns: self.pattern_features().0,
+            site: self.site,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.instances = snapshot.instances.into_iter().map(|i| (i.id, i)).collect();
+        self.joints = snapshot.joints.into_iter().map(|j| (j.id, j)).collect();
+        for component in snapshot.components {
+            self.components.add_component(component);
+        }
+        self.records.clear();
+        self.poped.clear();
+        self.ids = snapshot.ids;
+        self.patterns = snapshot.patterns.into_iter().map(|p| (p.id, p)).collect();
+        self.site = snapshot.site;
+        let ids: Vec<Uuid> = self.instances.keys().copied().collect();
+        self.refresh_index(&ids);
+    }
+
+    fn apply_log_entry(&mut self, entry: LogEntry) -> Result<(), OperationError> {
+        match entry {
+            LogEntry::AddComponent(component) => self.add_component(&component),
+            LogEntry::Push(op) => Record::push(self, op)?,
+            LogEntry::Pop(op) => {
+                self.commit_transaction();
+                self.end_gesture();
+                // the record was made before the snapshot, take it from the log
+                if let (Some(op), true) = (op, self.records.is_empty()) {
+                    self.records.push(op);
+                }
+                Record::pop(self);
+            }
+            LogEntry::Repush(op) => {
+                if let (Some(op), true) = (op, self.poped.is_empty()) {
+                    self.poped.push(op);
+                }
+                Record::repush(self);
+            }
+            LogEntry::BeginGesture => self.begin_gesture(),
+            LogEntry::EndGesture => self.end_gesture(),
+            LogEntry::BeginTransaction => self.begin_transaction(),
+            LogEntry::CommitTransaction => self.commit_transaction(),
+            LogEntry::RollbackTransaction => {
+                self.rollback_transaction();
+            }
+            LogEntry::SequentialIds(seed) => self.use_sequential_ids(seed),
+            LogEntry::SetSite(site) => self.set_site(site),
+            LogEntry::Snapshot(snapshot) => {
+                self.restore(snapshot.clone());
+                self.append_log(LogEntry::Snapshot(snapshot));
+            }
+        }
+        Ok(())
+    }
+
+    /// rebuild a design space from its log, starting at the last snapshot. The undo
+    /// history of the rebuilt space begins at that snapshot.
+    pub(crate) fn from_log(log: OperationLog) -> Result<DesignSpace, String> {
+        let mut space = DesignSpace::new();
+        let start = log.last_snapshot().unwrap_or(0);
+        let mut entries = log.entries;
+        let rest = entries.split_off(start);
+        space.log.entries = entries;
+        space.replaying = true;
+        for (i, entry) in rest.into_iter().enumerate() {
+            space
+                .apply_log_entry(entry)
+                .map_err(|e| format!("log entry {}: {}", start + i, e))?;
+        }
+        space.replaying = false;
+        Ok(space)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{AddInstance, ExtrudeAddLength, MoveInstance},
+    };
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn assert_same(a: &DesignSpace, b: &DesignSpace) {
+        let mut a_instances = a.get_instances();
+        a_instances.sort_by_key(|i| i.id);
+        let mut b_instances = b.get_instances();
+        b_instances.sort_by_key(|i| i.id);
+        assert_eq!(a_instances, b_instances);
+        assert_eq!(a.history(), b.history());
+        assert_eq!(a.log.entries.len(), b.log.entries.len());
+    }
+
+    fn edit(design: &mut DesignSpace) -> Uuid {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        design.add_component(component);
+        let add = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let id = add.instance.id;
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+        design.begin_gesture();
+        for x in 1..4 {
+            design
+                .push(DesignOperation::MoveInstance(MoveInstance {
+                    id,
+                    new_matrix: Isometry3::translation(x as f64, 0.0, 0.0),
+                    old_matrix: None,
+                }))
+                .unwrap();
+        }
+        design.end_gesture();
+        design.begin_transaction();
+        design
+            .push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: 1000,
+                new_matrix: Isometry3::translation(3.0, 0.0, 0.0),
+                old_matrix: None,
+            }))
+            .unwrap();
+        design.commit_transaction();
+        design.pop();
+        id
+    }
+
+    #[wasm_bindgen_test]
+    fn replay_test() {
+        let mut design = DesignSpace::new();
+        design.use_sequential_ids(1);
+        edit(&mut design);
+        let mut replayed = DesignSpace::from_log(design.export_log()).unwrap();
+        assert_same(&design, &replayed);
+        // the replayed generator continues after the logged instances
+        assert_eq!(design.ids.next_id(), replayed.ids.next_id());
+
+        // the log survives serialization
+        let json = serde_json::to_string(&design.export_log()).unwrap();
+        let replayed = DesignSpace::from_log(serde_json::from_str(&json).unwrap()).unwrap();
+        assert_same(&design, &replayed);
+    }
+
+    #[wasm_bindgen_test]
+    fn snapshot_replay_test() {
+        let mut design = DesignSpace::new();
+        let id = edit(&mut design);
+        for x in 0..SNAPSHOT_INTERVAL {
+            design
+                .push(DesignOperation::MoveInstance(MoveInstance {
+                    id,
+                    new_matrix: Isometry3::translation(0.0, x as f64, 0.0),
+                    old_matrix: None,
+                }))
+                .unwrap();
+        }
+        let snapshot = design.log.last_snapshot().unwrap();
+        assert!(snapshot > 0);
+        // snapshots hold the model, not the history, so the log grows linearly
+        let size = |design: &DesignSpace| serde_json::to_string(&design.log).unwrap().len();
+        let before = size(&design);
+        for x in 0..SNAPSHOT_INTERVAL {
+            design
+                .push(DesignOperation::MoveInstance(MoveInstance {
+                    id,
+                    new_matrix: Isometry3::translation(x as f64, 0.0, 0.0),
+                    old_matrix: None,
+                }))
+                .unwrap();
+        }
+        assert!(size(&design) < before * 2);
+
+        // undo back past the last snapshot, then redo one
+        for _ in 0..SNAPSHOT_INTERVAL + 5 {
+            design.pop();
+        }
+        design.repush();
+
+        let replayed = DesignSpace::from_log(design.export_log()).unwrap();
+        let mut instances = replayed.get_instances();
+        instances.sort_by_key(|i| i.id);
+        let mut expected = design.get_instances();
+        expected.sort_by_key(|i| i.id);
+        assert_eq!(instances, expected);
+        assert_eq!(replayed.log.last_snapshot(), design.log.last_snapshot());
+        assert_eq!(replayed.log.entries.len(), design.log.entries.len());
+    }
+
+    #[wasm_bindgen_test]
+    fn replay_error_test() {
+        let mut design = DesignSpace::new();
+        edit(&mut design);
+        let mut log = design.export_log();
+        // an edit of an instance that is not there cannot be replayed
+        log.entries
+            .push(LogEntry::Push(DesignOperation::MoveInstance(
+                MoveInstance {
+                    id: Uuid::new_v4(),
+                    new_matrix: Isometry3::identity(),
+                    old_matrix: None,
+                },
+            )));
+        assert!(DesignSpace::from_log(log).is_err());
+    }
+}
@@ -0,0 +1,546 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use super::{
+    joint::Joint,
+    operation::{AddInstance, AddJoint, DesignOperation, RemoveInstance, RemoveJoint, SetInstance},
+    pattern::SetPattern,
+    DesignSpace,
+};
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MergeConflict {
+    // 本地删除, 对方修改, 保留对方修改后的实例
+    DeletedByUs(String),
+    // 对方删除, 本地修改, 保留本地实例
+    DeletedByThem(String),
+    // 对方添加的连接, 其一端已被本地删除, 不保留该连接
+    DanglingJoint(String),
+    // 双方都修改了同一阵列, 保留较晚的修改及其副本
+    PatternEdited(String),
+}
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct MergeConflicts(pub Vec<MergeConflict>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Field {
+    Matrix,
+    Config,
+}
+
+// (逻辑时钟, 站点), 越大越晚
+type Stamp = (usize, u32);
+
+// 一个副本自共同基础以来的修改
+#[derive(Debug, Default)]
+struct Changes {
+    writes: HashMap<(Uuid, Field), Stamp>,
+    added: HashSet<Uuid>,
+    removed: HashSet<Uuid>,
+    added_joints: HashSet<Uuid>,
+    removed_joints: HashSet<Uuid>,
+    patterns: HashMap<Uuid, Stamp>,
+}
+
+impl Changes {
+    fn collect(space: &DesignSpace, base: usize) -> Self {
+        let mut changes = Changes::default();
+        let mut clock = base;
+        for op in space.records[base..].iter() {
+            changes.record(op, &mut clock, space.site);
+        }
+        changes
+    }
+
+    fn record(&mut self, op: &DesignOperation, clock: &mut usize, site: u32) {
+        *clock += 1;
+        let stamp = (*clock, site);
+        let mut write = |id: Uuid, fields: &[Field]| {
+            for field in fields {
+                self.writes.insert((id, *field), stamp);
+            }
+        };
+        match op {
+            DesignOperation::AddInstance(op) => {
+                self.added.insert(op.instance.id);
+            }
+            DesignOperation::RemoveInstance(op) => {
+                self.removed.insert(op.id);
+            }
+            DesignOperation::PostProcessInstance(op) => write(op.id, &[Field::Config]),
+            DesignOperation::ExtrudeAddLength(op) => write(op.id, &[Field::Matrix, Field::Config]),
+            DesignOperation::PanelAddSize(op) => write(op.id, &[Field::Matrix, Field::Config]),
+            DesignOperation::MoveInstance(op) => write(op.id, &[Field::Matrix]),
+            DesignOperation::SetInstance(op) => {
+                write(op.instance.id, &[Field::Matrix, Field::Config])
+            }
+            DesignOperation::ReplaceComponent(op) => write(op.id, &[Field::Config]),
+            DesignOperation::SplitExtrude(op) => {
+                write(op.left.id, &[Field::Matrix, Field::Config]);
+                self.added.insert(op.right.id);
+            }
+            DesignOperation::JoinExtrude(op) => {
+                write(op.instance.id, &[Field::Matrix, Field::Config]);
+                self.removed.insert(op.other);
+            }
+            DesignOperation::Pattern(op) => {
+                self.added.extend(op.feature.copies.iter());
+                self.patterns.insert(op.feature.id, stamp);
+            }
+            DesignOperation::EditPattern(op) => {
+                self.added.extend(op.feature.copies.iter());
+                self.removed.extend(op.old_copies.iter());
+                self.patterns.insert(op.feature.id, stamp);
+            }
+            DesignOperation::SetPattern(op) => {
+                self.patterns.insert(op.feature.id, stamp);
+            }
+            DesignOperation::AddJoint(op) => {
+                self.added_joints.insert(op.joint.id);
+            }
+            DesignOperation::RemoveJoint(op) => {
+                self.removed_joints.insert(op.id);
+            }
+            DesignOperation::Batch(op) => {
+                for op in op.operations.iter() {
+                    self.record(op, clock, site);
+                }
+            }
+        }
+    }
+
+    fn edited(&self, id: &Uuid) -> bool {
+        self.writes.keys().any(|(i, _)| i == id)
+    }
+}
+
+impl DesignSpace {
+    /// operations that bring this replica up to date with `theirs`, both sharing the first
+    /// `base` records. Edits of the same field keep the later one by logical clock then site.
+    pub(crate) fn merge_operations(
+        &self,
+        base: usize,
+        theirs: &DesignSpace,
+    ) -> Result<(Vec<DesignOperation>, Vec<MergeConflict>), String> {
+        if self.records.len() < base || theirs.records.len() < base {
+            return Err(format!("invalid merge base: {}", base));
+        }
+        let ours_changes = Changes::collect(self, base);
+        let theirs_changes = Changes::collect(theirs, base);
+
+        let mut ids: Vec<&Uuid> = theirs_changes
+            .writes
+            .keys()
+            .map(|(id, _)| id)
+            .chain(theirs_changes.added.iter())
+            .chain(theirs_changes.removed.iter())
+            .chain(ours_changes.removed.iter())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        ids.sort();
+
+        let mut operations = Vec::new();
+        let mut conflicts = Vec::new();
+
+        // a pattern changed by both keeps the later edit, the copies of the other are dropped
+        let mut pattern_operations = Vec::new();
+        let mut skipped: HashSet<&Uuid> = HashSet::new();
+        let mut dropped = Vec::new();
+        let mut patterns: Vec<(&Uuid, &Stamp)> = theirs_changes.patterns.iter().collect();
+        patterns.sort();
+        for (id, stamp) in patterns {
+            let Some(feature) = theirs.patterns.get(id) else {
+                continue;
+            };
+            match (ours_changes.patterns.get(id), self.patterns.get(id)) {
+                (Some(ours_stamp), Some(ours)) => {
+                    conflicts.push(MergeConflict::PatternEdited(id.to_string()));
+                    if stamp < ours_stamp {
+                        skipped.extend(feature.copies.iter().filter(|c| !ours.copies.contains(c)));
+                        continue;
+                    }
+                    dropped.extend(ours.copies.iter().filter(|c| !feature.copies.contains(c)));
+                }
+                _ if self.patterns.get(id) == Some(feature) => continue,
+                _ => {}
+            }
+            pattern_operations.push(DesignOperation::SetPattern(SetPattern {
+                feature: feature.clone(),
+                old_feature: None,
+            }));
+        }
+
+        for id in ids {
+            if skipped.contains(id) {
+                continue;
+            }
+            match (self.instances.get(id), theirs.instances.get(id)) {
+                (Some(_), None) if theirs_changes.removed.contains(id) => {
+                    if ours_changes.edited(id) {
+                        conflicts.push(MergeConflict::DeletedByThem(id.to_string()));
+                    } else {
+                        operations.push(DesignOperation::RemoveInstance(RemoveInstance {
+                            id: *id,
+                            removed_instance: None,
+                            removed_joints: Vec::new(),
+                        }));
+                    }
+                }
+                (None, Some(instance)) => {
+                    if ours_changes.removed.contains(id) {
+                        if !theirs_changes.edited(id) {
+                            continue;
+                        }
+                        conflicts.push(MergeConflict::DeletedByUs(id.to_string()));
+                    }
+                    operations.push(DesignOperation::AddInstance(AddInstance {
+                        instance: instance.clone(),
+                    }));
+                }
+                (Some(ours), Some(instance)) => {
+                    let wins = |field: Field| match (
+                        ours_changes.writes.get(&(*id, field)),
+                        theirs_changes.writes.get(&(*id, field)),
+                    ) {
+                        (_, None) => false,
+                        (None, Some(_)) => true,
+                        (Some(ours), Some(theirs)) => theirs > ours,
+                    };
+                    let mut merged = ours.clone();
+                    if wins(Field::Matrix) {
+                        merged.matrix = instance.matrix;
+                    }
+                    if wins(Field::Config) {
+                        // the config only fits the component it was made for
+                        merged.component_label = instance.component_label.clone();
+                        merged.component_type = instance.component_type;
+                        merged.config = instance.config.clone();
+                    }
+                    if merged != *ours {
+                        operations.push(DesignOperation::SetInstance(SetInstance {
+                            instance: merged,
+                            old_instance: None,
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+        dropped.sort();
+        for id in dropped {
+            if self.instances.contains_key(id) {
+                operations.push(DesignOperation::RemoveInstance(RemoveInstance {
+                    id: *id,
+                    removed_instance: None,
+                    removed_joints: Vec::new(),
+                }));
+            }
+        }
+
+        // instances after the instance edits above, removing one also removes its joints
+        let mut exists: HashSet<Uuid> = self.instances.keys().copied().collect();
+        let mut readded = HashSet::new();
+        for op in operations.iter() {
+            match op {
+                DesignOperation::RemoveInstance(op) => {
+                    exists.remove(&op.id);
+                }
+                DesignOperation::AddInstance(op) => {
+                    exists.insert(op.instance.id);
+                    readded.insert(op.instance.id);
+                }
+                _ => {}
+            }
+        }
+        let kept = |joint: &Joint| exists.contains(&joint.a) && exists.contains(&joint.b);
+
+        // their new joints, and their joints of instances we deleted and get back
+        let mut joints: Vec<&Uuid> = theirs_changes
+            .added_joints
+            .iter()
+            .chain(theirs_changes.removed_joints.iter())
+            .chain(
+                theirs
+                    .joints
+                    .values()
+                    .filter(|j| readded.contains(&j.a) || readded.contains(&j.b))
+                    .map(|j| &j.id),
+            )
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        joints.sort();
+        for id in joints {
+            match (self.joints.get(id), theirs.joints.get(id)) {
+                (None, Some(joint)) if !ours_changes.removed_joints.contains(id) => {
+                    if kept(joint) {
+                        operations.push(DesignOperation::AddJoint(AddJoint {
+                            joint: joint.clone(),
+                        }));
+                    } else {
+                        conflicts.push(MergeConflict::DanglingJoint(id.to_string()));
+                    }
+                }
+                (Some(joint), None) if kept(joint) => {
+                    operations.push(DesignOperation::RemoveJoint(RemoveJoint {
+                        id: *id,
+                        removed_joint: None,
+                    }))
+                }
+                _ => {}
+            }
+        }
+        operations.extend(pattern_operations);
+        Ok((operations, conflicts))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::{
+            operation::{
+                add_extrude_instance, add_joint, remove_instance, ExtrudeAddLength, MoveInstance,
+            },
+            pattern::{edit_pattern, pattern_instances, PatternKind},
+        },
+        instance::{Instance, InstanceConfig},
+        Translation,
+    };
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn sorted(space: &DesignSpace) -> Vec<Instance> {
+        let mut instances = space.get_instances();
+        instances.sort_by_key(|i| i.id);
+        instances
+    }
+
+    fn move_op(id: Uuid, x: f64) -> DesignOperation {
+        DesignOperation::MoveInstance(MoveInstance {
+            id,
+            new_matrix: Isometry3::translation(x, 0.0, 0.0),
+            old_matrix: None,
+        })
+    }
+
+    #[wasm_bindgen_test]
+    fn merge_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+
+        let mut a = DesignSpace::new();
+        a.use_sequential_ids(1);
+        a.set_site(1);
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            let op = add_extrude_instance(&mut a, component, 100000).unwrap();
+            ids.extend(op.instance_ids());
+            a.push(op).unwrap();
+        }
+        let base = a.records.len();
+        let mut b = DesignSpace::replay(a.export_log()).unwrap();
+        b.use_sequential_ids(2);
+        b.set_site(2);
+
+        // both move ids[0], b later; a lengthens ids[1] while b moves it
+        a.push(move_op(ids[0], 1.0)).unwrap();
+        b.push(move_op(ids[1], 3.0)).unwrap();
+        b.push(move_op(ids[0], 2.0)).unwrap();
+        a.push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+            id: ids[1],
+            dlength: 1000,
+            new_matrix: Isometry3::identity(),
+            old_matrix: None,
+        }))
+        .unwrap();
+        // delete vs edit on ids[2] and ids[3]
+        a.push(DesignOperation::RemoveInstance(RemoveInstance {
+            id: ids[2],
+            removed_instance: None,
+            removed_joints: Vec::new(),
+        }))
+        .unwrap();
+        b.push(move_op(ids[2], 4.0)).unwrap();
+        b.push(DesignOperation::RemoveInstance(RemoveInstance {
+            id: ids[3],
+            removed_instance: None,
+            removed_joints: Vec::new(),
+        }))
+        .unwrap();
+        a.push(move_op(ids[3], 5.0)).unwrap();
+        // each adds a new instance
+        let op = add_extrude_instance(&mut a, component, 100000).unwrap();
+        a.push(op).unwrap();
+        let op = add_extrude_instance(&mut b, component, 100000).unwrap();
+        b.push(op).unwrap();
+
+        // each replica merges the other's edits as received before merging
+        // the site travels with the log
+        let a_sent = DesignSpace::replay(a.export_log()).unwrap();
+        assert_eq!(a_sent.site, 1);
+        let a_conflicts = a.merge(base, &b).unwrap();
+        let b_conflicts = b.merge(base, &a_sent).unwrap();
+        assert_eq!(
+            a_conflicts,
+            MergeConflicts(vec![
+                MergeConflict::DeletedByUs(ids[2].to_string()),
+                MergeConflict::DeletedByThem(ids[3].to_string()),
+            ])
+        );
+        assert_eq!(b_conflicts.0.len(), 2);
+
+        // the replicas converge
+        assert_eq!(sorted(&a), sorted(&b));
+        assert_eq!(a.instances.len(), 6);
+        assert_eq!(
+            a.instances[&ids[0]].matrix,
+            Isometry3::translation(2.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            a.instances[&ids[1]].config,
+            InstanceConfig::default_extrude(101000)
+        );
+        assert_eq!(a.instances[&ids[1]].matrix, Isometry3::identity());
+        assert_eq!(
+            a.instances[&ids[2]].matrix,
+            Isometry3::translation(4.0, 0.0, 0.0)
+        );
+
+        // the merge is one undoable step
+        a.pop();
+        assert_eq!(
+            a.instances[&ids[0]].matrix,
+            Isometry3::translation(1.0, 0.0, 0.0)
+        );
+        assert_eq!(a.instances.len(), 4);
+        assert!(a.merge(100, &b).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn merge_joint_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+
+        let mut a = DesignSpace::new();
+        a.use_sequential_ids(1);
+        a.set_site(1);
+        let mut instances = Vec::new();
+        for _ in 0..3 {
+            let op = add_extrude_instance(&mut a, component, 100000).unwrap();
+            instances.push(match &op {
+                DesignOperation::AddInstance(op) => op.instance.clone(),
+                _ => unreachable!(),
+            });
+            a.push(op).unwrap();
+        }
+        let (x, y, z) = (&instances[0], &instances[1], &instances[2]);
+        let op = add_joint(&mut a, x, z);
+        a.push(op).unwrap();
+        let base = a.records.len();
+        let mut b = DesignSpace::replay(a.export_log()).unwrap();
+        b.use_sequential_ids(2);
+        b.set_site(2);
+
+        // a joins x to y which b deletes
+        let op = add_joint(&mut a, x, y);
+        let xy = op.joint_ids()[0];
+        a.push(op).unwrap();
+        b.push(remove_instance(y)).unwrap();
+        // a deletes z with its joint while b moves it
+        a.push(remove_instance(z)).unwrap();
+        b.push(move_op(z.id, 1.0)).unwrap();
+
+        let a_sent = DesignSpace::replay(a.export_log()).unwrap();
+        let a_conflicts = a.merge(base, &b).unwrap();
+        let b_conflicts = b.merge(base, &a_sent).unwrap();
+        assert_eq!(
+            a_conflicts,
+            MergeConflicts(vec![MergeConflict::DeletedByUs(z.id.to_string())])
+        );
+        assert!(b_conflicts
+            .0
+            .contains(&MergeConflict::DanglingJoint(xy.to_string())));
+
+        // z comes back with its joint, the joint to the deleted y is gone
+        let joints = |space: &DesignSpace| {
+            let mut joints = space.get_joints();
+            joints.sort_by_key(|j| j.id);
+            joints
+        };
+        assert_eq!(sorted(&a), sorted(&b));
+        assert_eq!(joints(&a), joints(&b));
+        assert_eq!(a.joints.len(), 1);
+        assert!(a.get_joints()[0].connects(&x.id, &z.id));
+    }
+
+    #[wasm_bindgen_test]
+    fn merge_pattern_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let linear = |count| PatternKind::Linear {
+            direction: Translation {
+                x: 0.,
+                y: 0.,
+                z: 1.,
+            },
+            count,
+            pitch: 30000,
+        };
+
+        let mut a = DesignSpace::new();
+        a.use_sequential_ids(1);
+        a.set_site(1);
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let op = add_extrude_instance(&mut a, component, 100000).unwrap();
+            ids.push(op.instance_ids()[0].to_string());
+            a.push(op).unwrap();
+        }
+        let op = pattern_instances(&mut a, ids[..1].to_vec(), linear(3)).unwrap();
+        a.push(op).unwrap();
+        let pattern = a.pattern_features().0[0].id;
+        let base = a.records.len();
+        let mut b = DesignSpace::replay(a.export_log()).unwrap();
+        b.use_sequential_ids(2);
+        b.set_site(2);
+
+        // both edit the pattern, b later by site; b also patterns the other instance
+        let op = edit_pattern(&mut a, pattern.to_string(), linear(4)).unwrap();
+        a.push(op).unwrap();
+        let op = edit_pattern(&mut b, pattern.to_string(), linear(2)).unwrap();
+        b.push(op).unwrap();
+        let op = pattern_instances(&mut b, ids[1..].to_vec(), linear(2)).unwrap();
+        b.push(op).unwrap();
+
+        let a_sent = DesignSpace::replay(a.export_log()).unwrap();
+        let a_conflicts = a.merge(base, &b).unwrap();
+        let b_conflicts = b.merge(base, &a_sent).unwrap();
+        let edited = MergeConflict::PatternEdited(pattern.to_string());
+        assert_eq!(a_conflicts, MergeConflicts(vec![edited.clone()]));
+        assert_eq!(b_conflicts, MergeConflicts(vec![edited]));
+
+        // the replicas converge on b's edit and both patterns
+        assert_eq!(sorted(&a), sorted(&b));
+        assert_eq!(a.pattern_features().0, b.pattern_features().0);
+        assert_eq!(a.patterns.len(), 2);
+        assert_eq!(a.patterns[&pattern].kind, linear(2));
+        assert_eq!(a.instances.len(), 4);
+
+        // the merged pattern can be edited again
+        let op = edit_pattern(&mut a, pattern.to_string(), linear(3)).unwrap();
+        a.push(op).unwrap();
+        assert_eq!(a.instances.len(), 5);
+    }
+}
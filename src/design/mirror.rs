@@ -0,0 +1,216 @@
+use nalgebra::{
+    Isometry3, Matrix3, Point3, Rotation3, Translation3, Unit, UnitQuaternion, Vector3,
+};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::{component::ComponentType, instance::InstanceConfig, unit::snap_position, Translation};
+
+use super::{
+    id::parse_ids,
+    joint::Joint,
+    operation::{AddInstance, AddJoint, Batch, DesignOperation},
+    DesignSpace,
+};
+
+/// placement of the mirror image of `matrix` across the plane through `origin` with `normal`.
+/// A reflection is not a rotation, so the image is also turned end to end along local x,
+/// which looks the same for a centred prism such as an extrusion or a panel.
+fn mirrored(
+    matrix: &Isometry3<f64>,
+    origin: &Point3<f64>,
+    normal: &Unit<Vector3<f64>>,
+) -> Isometry3<f64> {
+    let reflection = Matrix3::identity() - 2. * normal.as_ref() * normal.transpose();
+    let rotation = reflection
+        * matrix.rotation.to_rotation_matrix().matrix()
+        * Matrix3::from_diagonal(&Vector3::new(-1., 1., 1.));
+    let p: Point3<f64> = matrix.translation.vector.into();
+    let p = p - 2. * (p - origin).dot(normal) * normal.as_ref();
+    Isometry3::from_parts(
+        Translation3::from(p.coords),
+        UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(rotation)),
+    )
+}
+
+/// mirror copies of the instances `ids` and the joints between them across a plane,
+/// end machining of extrusions follows the ends they are mirrored to. Only extrusions and
+/// panels are mirrored, other parts may be handed and a turned copy is not their mirror image.
+#[wasm_bindgen]
+pub fn mirror_instances(
+    design: &mut DesignSpace,
+    ids: Vec<String>,
+    origin: Translation,
+    normal: Translation,
+) -> Result<DesignOperation, String> {
+    let normal = Unit::try_new(
+        Vector3::new(normal.x, normal.y, normal.z).cast(),
+        f64::EPSILON,
+    )
+    .ok_or_else(|| "invalid mirror plane".to_string())?;
+    let origin = Point3::new(origin.x, origin.y, origin.z).map(|v| snap_position(v as f64));
+    let mut ids = parse_ids(&ids)?;
+    let mut seen = HashSet::new();
+    ids.retain(|id| seen.insert(*id));
+    if ids.is_empty() {
+        return Err("nothing to mirror".to_string());
+    }
+
+    let mut copies = HashMap::new();
+    let mut operations = Vec::new();
+    for id in ids {
+        let instance = design
+            .instances
+            .get(&id)
+            .ok_or_else(|| format!("instance not found: {}", id))?;
+        // 截面不变, 只沿局部 x 翻转, 对型材与面板就是镜像
+        if !matches!(
+            instance.component_type,
+            ComponentType::Extrude | ComponentType::Panel
+        ) {
+            return Err(format!(
+                "cannot mirror {}: only extrusions and panels are mirror-symmetric",
+                instance.component_label
+            ));
+        }
+        let mut copy = instance.clone();
+        copy.id = design.ids.next_id();
+        copy.matrix = mirrored(&instance.matrix, &origin, &normal);
+        if let InstanceConfig::Extrude(e) = &mut copy.config {
+            e.swap_ends();
+        }
+        copies.insert(id, copy.id);
+        operations.push(DesignOperation::AddInstance(AddInstance { instance: copy }));
+    }
+
+    let mut joints: Vec<&Joint> = design.joints.values().collect();
+    joints.sort_by_key(|j| j.id);
+    let pairs: Vec<(Uuid, Uuid)> = joints
+        .into_iter()
+        .filter_map(|j| Some((*copies.get(&j.a)?, *copies.get(&j.b)?)))
+        .collect();
+    for (a, b) in pairs {
+        operations.push(DesignOperation::AddJoint(AddJoint {
+            joint: Joint::new(design.ids.next_id(), a, b),
+        }));
+    }
+    Ok(DesignOperation::Batch(Batch { operations }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{add_extrude_instance, add_joint, add_normal_instance},
+        instance::{BevelCutConfig, Instance, LENGTH_UNIT},
+    };
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn ends(instance: &Instance) -> (Point3<f32>, Point3<f32>) {
+        let half = match &instance.config {
+            InstanceConfig::Extrude(e) => e.length as f32 / LENGTH_UNIT / 2.,
+            _ => unreachable!(),
+        };
+        (
+            instance.isometry() * Point3::new(-half, 0.0, 0.0),
+            instance.isometry() * Point3::new(half, 0.0, 0.0),
+        )
+    }
+
+    #[wasm_bindgen_test]
+    fn mirror_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        let mut ids = Vec::new();
+        let matrices = [
+            Isometry3::translation(0.5, 0.0, 0.0),
+            Isometry3::new(Vector3::new(0.2, 0.3, 0.1), Vector3::new(0.3, 0.5, 0.7)),
+        ];
+        for matrix in matrices {
+            let mut op = add_extrude_instance(&mut design, bar, 100000).unwrap();
+            if let DesignOperation::AddInstance(op) = &mut op {
+                op.instance.matrix = matrix;
+                if let InstanceConfig::Extrude(e) = &mut op.instance.config {
+                    e.drill_left = true;
+                    e.counterbore_right = 2;
+                    e.bevel_cut = Some(BevelCutConfig::TopToBottom);
+                }
+            }
+            ids.push(op.instance_ids()[0]);
+            design.push(op).unwrap();
+        }
+        let (a, b) = (
+            design.instances[&ids[0]].clone(),
+            design.instances[&ids[1]].clone(),
+        );
+        let op = add_joint(&mut design, &a, &b);
+        design.push(op).unwrap();
+
+        let op = mirror_instances(
+            &mut design,
+            // a repeated id is mirrored once
+            ids.iter()
+                .chain(ids.iter())
+                .map(|id| id.to_string())
+                .collect(),
+            Translation::identity(),
+            Translation {
+                x: 1.,
+                y: 0.,
+                z: 0.,
+            },
+        )
+        .unwrap();
+        let copies = op.instance_ids();
+        design.push(op).unwrap();
+        assert_eq!(design.instances.len(), 4);
+        assert_eq!(design.joints.len(), 2);
+
+        let reflect = |p: Point3<f32>| Point3::new(-p.x, p.y, p.z);
+        for (id, copy) in ids.iter().zip(copies.iter()) {
+            let (source, copy) = (&design.instances[id], &design.instances[copy]);
+            let (left, right) = ends(source);
+            let (copy_left, copy_right) = ends(copy);
+            // the drilled left end is mirrored to the right end of the copy
+            assert!((reflect(left) - copy_right).norm() < 1e-5);
+            assert!((reflect(right) - copy_left).norm() < 1e-5);
+            match &copy.config {
+                InstanceConfig::Extrude(e) => {
+                    assert!(e.drill_right && !e.drill_left);
+                    assert_eq!((e.counterbore_left, e.counterbore_right), (2, 0));
+                    assert_eq!(e.bevel_cut, Some(BevelCutConfig::TopToBottom));
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(
+            design.instances[&copies[0]].matrix,
+            Isometry3::translation(-0.5, 0.0, 0.0)
+        );
+
+        design.pop();
+        assert_eq!(design.instances.len(), 2);
+        assert_eq!(design.joints.len(), 1);
+        let x = Translation {
+            x: 1.,
+            y: 0.,
+            z: 0.,
+        };
+        assert!(
+            mirror_instances(&mut design, Vec::new(), Translation::identity(), x.clone()).is_err()
+        );
+
+        // a handed part is refused
+        let accessory = lib.components.get("Accessory-test").unwrap();
+        let op = add_normal_instance(&mut design, accessory).unwrap();
+        let id = op.instance_ids()[0].to_string();
+        design.push(op).unwrap();
+        assert!(mirror_instances(&mut design, vec![id], Translation::identity(), x).is_err());
+    }
+}
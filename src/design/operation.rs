@@ -1,13 +1,20 @@
-use nalgebra::Isometry3;
+use nalgebra::{Isometry3, Vector3};
 use serde::{Deserialize, Serialize};
 use tsify::Tsify;
 use uuid::Uuid;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::{
-    component::Component,
-    design::DesignSpace,
-    instance::{ExtrudeConfig, Instance, InstanceConfig},
+    component::{check_slot_compatible, Component, ComponentType},
+    design::{
+        id::IdGenerator,
+        joint::Joint,
+        pattern::{EditPattern, Pattern, SetPattern},
+        split::{JoinExtrude, SplitExtrude},
+        DesignSpace,
+    },
+    instance::{ExtrudeConfig, Instance, InstanceConfig, POSITION_LENGTH_UNIT},
+    unit::placement,
     Quaternion, Translation,
 };
 
@@ -20,27 +27,37 @@ pub trait Operation {
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddInstance {
     pub(crate) instance: Instance,
 }
 
 impl AddInstance {
-    pub fn default_component(component: &Component) -> Self {
+    pub(crate) fn default_component(ids: &mut IdGenerator, component: &Component) -> Self {
         AddInstance {
-            instance: Instance::default_component(component),
+            instance: Instance::default_component(ids.next_id(), component),
         }
     }
 
-    pub fn extrude(component: &Component, length: u32) -> Option<Self> {
+    pub(crate) fn extrude(
+        ids: &mut IdGenerator,
+        component: &Component,
+        length: u32,
+    ) -> Option<Self> {
         Some(AddInstance {
-            instance: Instance::default_extrude(component, length)?,
+            instance: Instance::default_extrude(ids.next_id(), component, length)?,
         })
     }
 
-    pub fn panel(component: &Component, x: u32, y: u32, thickness: u32) -> Option<Self> {
+    pub(crate) fn panel(
+        ids: &mut IdGenerator,
+        component: &Component,
+        x: u32,
+        y: u32,
+        thickness: u32,
+    ) -> Option<Self> {
         Some(AddInstance {
-            instance: Instance::default_panel(component, x, y, thickness)?,
+            instance: Instance::default_panel(ids.next_id(), component, x, y, thickness)?,
         })
     }
 }
@@ -64,10 +81,11 @@ impl Operation for AddInstance {
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RemoveInstance {
     pub(crate) id: Uuid,
     pub(crate) removed_instance: Option<Instance>,
+    pub(crate) removed_joints: Vec<Joint>, // 随实例一起删除的连接
 }
 
 impl Operation for RemoveInstance {
@@ -77,12 +95,14 @@ impl Operation for RemoveInstance {
         let removed_instance = target.instances.remove(&self.id);
         if let Some(removed_instance) = removed_instance {
             self.removed_instance.replace(removed_instance);
+            self.removed_joints = target.detach_joints(&self.id);
         }
     }
 
     fn inverse(&mut self, target: &mut Self::Target) {
         if let Some(instance) = self.removed_instance.take() {
             target.instances.entry(instance.id).or_insert(instance);
+            target.restore_joints(std::mem::take(&mut self.removed_joints));
         }
     }
 
@@ -92,7 +112,7 @@ impl Operation for RemoveInstance {
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PostProcessInstance {
     pub(crate) id: Uuid,
     pub(crate) config: InstanceConfig,
@@ -119,9 +139,10 @@ impl Operation for PostProcessInstance {
     }
 
     fn inverse(&mut self, target: &mut Self::Target) {
+        // nothing was cached if the instance was missing when operating
         let instance = target.instances.get_mut(&self.id);
-        if let Some(instance) = instance {
-            instance.config = self.config_cache.take().unwrap();
+        if let (Some(instance), Some(config)) = (instance, self.config_cache.take()) {
+            instance.config = config;
         }
     }
 
@@ -131,12 +152,12 @@ impl Operation for PostProcessInstance {
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExtrudeAddLength {
     pub(crate) id: Uuid,
     pub(crate) dlength: i32,
-    pub(crate) new_matrix: Isometry3<f32>,
-    pub(crate) old_matrix: Option<Isometry3<f32>>,
+    pub(crate) new_matrix: Isometry3<f64>,
+    pub(crate) old_matrix: Option<Isometry3<f64>>,
 }
 
 impl Operation for ExtrudeAddLength {
@@ -155,15 +176,18 @@ impl Operation for ExtrudeAddLength {
 
     fn inverse(&mut self, target: &mut Self::Target) {
         let instance = target.instances.get_mut(&self.id);
-        if let Some(instance) = instance {
+        if let (Some(instance), Some(old_matrix)) = (instance, self.old_matrix) {
             if let InstanceConfig::Extrude(e) = &mut instance.config {
                 e.length = ((e.length as i32) - self.dlength) as u32;
-                instance.matrix = self.old_matrix.unwrap();
+                instance.matrix = old_matrix;
             }
         }
     }
 
     fn compress(&mut self, target: &Self) -> bool {
+        if self.id != target.id {
+            return false;
+        }
         self.dlength += target.dlength;
         self.new_matrix = target.new_matrix;
         true
@@ -171,14 +195,14 @@ impl Operation for ExtrudeAddLength {
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PanelAddSize {
     pub(crate) id: Uuid,
     pub(crate) dx: i32,
     pub(crate) dy: i32,
     pub(crate) dthickness: i32,
-    pub(crate) new_matrix: Isometry3<f32>,
-    pub(crate) old_matrix: Option<Isometry3<f32>>,
+    pub(crate) new_matrix: Isometry3<f64>,
+    pub(crate) old_matrix: Option<Isometry3<f64>>,
 }
 
 impl Operation for PanelAddSize {
@@ -198,17 +222,20 @@ impl Operation for PanelAddSize {
 
     fn inverse(&mut self, target: &mut Self::Target) {
         let instance = target.instances.get_mut(&self.id);
-        if let Some(instance) = instance {
+        if let (Some(instance), Some(old_matrix)) = (instance, self.old_matrix) {
             if let InstanceConfig::Panel(p) = &mut instance.config {
                 p.x = ((p.x as i32) - self.dx) as u32;
                 p.y = ((p.y as i32) - self.dy) as u32;
                 p.thickness = ((p.thickness as i32) - self.dthickness) as u32;
-                instance.matrix = self.old_matrix.unwrap();
+                instance.matrix = old_matrix;
             }
         }
     }
 
     fn compress(&mut self, target: &Self) -> bool {
+        if self.id != target.id {
+            return false;
+        }
         self.dx += target.dx;
         self.dy += target.dy;
         self.dthickness += target.dthickness;
@@ -218,11 +245,11 @@ impl Operation for PanelAddSize {
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MoveInstance {
     pub(crate) id: Uuid,
-    pub(crate) new_matrix: Isometry3<f32>,
-    pub(crate) old_matrix: Option<Isometry3<f32>>,
+    pub(crate) new_matrix: Isometry3<f64>,
+    pub(crate) old_matrix: Option<Isometry3<f64>>,
 }
 
 impl Operation for MoveInstance {
@@ -238,21 +265,168 @@ impl Operation for MoveInstance {
 
     fn inverse(&mut self, target: &mut Self::Target) {
         let instance = target.instances.get_mut(&self.id);
-        if let Some(instance) = instance {
-            instance.matrix = self.old_matrix.unwrap();
+        if let (Some(instance), Some(old_matrix)) = (instance, self.old_matrix) {
+            instance.matrix = old_matrix;
         }
     }
 
     fn compress(&mut self, target: &Self) -> bool {
+        if self.id != target.id {
+            return false;
+        }
         self.new_matrix = target.new_matrix;
         true
     }
 }
 
+/// replace every field of an existing instance
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetInstance {
+    pub(crate) instance: Instance,
+    pub(crate) old_instance: Option<Instance>,
+}
+
+impl Operation for SetInstance {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        if let Some(instance) = target.instances.get_mut(&self.instance.id) {
+            self.old_instance
+                .replace(std::mem::replace(instance, self.instance.clone()));
+        }
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        if let Some(old_instance) = self.old_instance.take() {
+            target.instances.insert(old_instance.id, old_instance);
+        }
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+/// swap the component of an instance, keeping its placement
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaceComponent {
+    pub(crate) id: Uuid,
+    pub(crate) component_label: String,
+    pub(crate) component_type: ComponentType,
+    pub(crate) config: InstanceConfig,
+    pub(crate) old_component: Option<(String, ComponentType, InstanceConfig)>,
+}
+
+impl Operation for ReplaceComponent {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        if let Some(instance) = target.instances.get_mut(&self.id) {
+            self.old_component.replace((
+                std::mem::replace(&mut instance.component_label, self.component_label.clone()),
+                std::mem::replace(&mut instance.component_type, self.component_type),
+                std::mem::replace(&mut instance.config, self.config.clone()),
+            ));
+        }
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        if let Some(instance) = target.instances.get_mut(&self.id) {
+            if let Some((label, component_type, config)) = self.old_component.take() {
+                instance.component_label = label;
+                instance.component_type = component_type;
+                instance.config = config;
+            }
+        }
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddJoint {
+    pub(crate) joint: Joint,
+}
+
+impl Operation for AddJoint {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        target.joints.insert(self.joint.id, self.joint.clone());
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        target.joints.remove(&self.joint.id);
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveJoint {
+    pub(crate) id: Uuid,
+    pub(crate) removed_joint: Option<Joint>,
+}
+
+impl Operation for RemoveJoint {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        if let Some(removed_joint) = target.joints.remove(&self.id) {
+            self.removed_joint.replace(removed_joint);
+        }
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        if let Some(joint) = self.removed_joint.take() {
+            target.joints.entry(joint.id).or_insert(joint);
+        }
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+/// operations applied and undone as one history entry
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    pub(crate) operations: Vec<DesignOperation>,
+}
+
+impl Operation for Batch {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        for op in self.operations.iter_mut() {
+            op.operate(target);
+        }
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        for op in self.operations.iter_mut().rev() {
+            op.inverse(target);
+        }
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
 #[allow(non_snake_case, clippy::empty_docs)]
 pub mod allow_non_snake_case {
     use super::*;
-    #[derive(Debug, Tsify, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
     #[tsify(into_wasm_abi, from_wasm_abi)]
     pub enum DesignOperation {
         AddInstance(AddInstance),
@@ -261,6 +435,16 @@ pub mod allow_non_snake_case {
         ExtrudeAddLength(ExtrudeAddLength),
         PanelAddSize(PanelAddSize),
         MoveInstance(MoveInstance),
+        SetInstance(SetInstance),
+        ReplaceComponent(ReplaceComponent),
+        SplitExtrude(SplitExtrude),
+        JoinExtrude(JoinExtrude),
+        Pattern(Pattern),
+        EditPattern(EditPattern),
+        SetPattern(SetPattern),
+        AddJoint(AddJoint),
+        RemoveJoint(RemoveJoint),
+        Batch(Batch),
         // AddConstraint,
         // RemoveConstraint,
         // ConfigConstraint,
@@ -268,9 +452,63 @@ pub mod allow_non_snake_case {
         // RemoveInput,
         // ConfigInput,
     }
+
+    // 改变型材长度时保持不动的位置
+    #[derive(Debug, Clone, Copy, Tsify, Serialize, Deserialize, PartialEq, Eq)]
+    #[tsify(into_wasm_abi, from_wasm_abi)]
+    pub enum ExtrudeAnchor {
+        Left,   // 左端 (局部 -x)
+        Right,  // 右端 (局部 +x)
+        Center, // 中心
+    }
 }
 
-pub use allow_non_snake_case::DesignOperation;
+pub use allow_non_snake_case::{DesignOperation, ExtrudeAnchor};
+
+impl DesignOperation {
+    /// instances the operation may create, change or remove
+    pub(crate) fn instance_ids(&self) -> Vec<Uuid> {
+        match self {
+            DesignOperation::AddInstance(op) => vec![op.instance.id],
+            DesignOperation::RemoveInstance(op) => vec![op.id],
+            DesignOperation::PostProcessInstance(op) => vec![op.id],
+            DesignOperation::ExtrudeAddLength(op) => vec![op.id],
+            DesignOperation::PanelAddSize(op) => vec![op.id],
+            DesignOperation::MoveInstance(op) => vec![op.id],
+            DesignOperation::SetInstance(op) => vec![op.instance.id],
+            DesignOperation::ReplaceComponent(op) => vec![op.id],
+            DesignOperation::SplitExtrude(op) => vec![op.left.id, op.right.id],
+            DesignOperation::JoinExtrude(op) => vec![op.instance.id, op.other],
+            DesignOperation::Pattern(op) => op.feature.copies.clone(),
+            DesignOperation::EditPattern(op) => op
+                .feature
+                .copies
+                .iter()
+                .chain(op.old_copies.iter())
+                .copied()
+                .collect(),
+            DesignOperation::SetPattern(_)
+            | DesignOperation::AddJoint(_)
+            | DesignOperation::RemoveJoint(_) => vec![],
+            DesignOperation::Batch(op) => op
+                .operations
+                .iter()
+                .flat_map(|op| op.instance_ids())
+                .collect(),
+        }
+    }
+
+    /// joints the operation adds
+    pub(crate) fn joint_ids(&self) -> Vec<Uuid> {
+        match self {
+            DesignOperation::AddJoint(op) => vec![op.joint.id],
+            DesignOperation::Batch(op) => {
+                op.operations.iter().flat_map(|op| op.joint_ids()).collect()
+            }
+            _ => vec![],
+        }
+    }
+}
 
 impl Operation for DesignOperation {
     type Target = DesignSpace;
@@ -283,6 +521,16 @@ impl Operation for DesignOperation {
             DesignOperation::ExtrudeAddLength(op) => op.operate(target),
             DesignOperation::PanelAddSize(op) => op.operate(target),
             DesignOperation::MoveInstance(op) => op.operate(target),
+            DesignOperation::SetInstance(op) => op.operate(target),
+            DesignOperation::ReplaceComponent(op) => op.operate(target),
+            DesignOperation::SplitExtrude(op) => op.operate(target),
+            DesignOperation::JoinExtrude(op) => op.operate(target),
+            DesignOperation::Pattern(op) => op.operate(target),
+            DesignOperation::EditPattern(op) => op.operate(target),
+            DesignOperation::SetPattern(op) => op.operate(target),
+            DesignOperation::AddJoint(op) => op.operate(target),
+            DesignOperation::RemoveJoint(op) => op.operate(target),
+            DesignOperation::Batch(op) => op.operate(target),
         }
     }
 
@@ -294,6 +542,16 @@ impl Operation for DesignOperation {
             DesignOperation::ExtrudeAddLength(op) => op.inverse(target),
             DesignOperation::PanelAddSize(op) => op.inverse(target),
             DesignOperation::MoveInstance(op) => op.inverse(target),
+            DesignOperation::SetInstance(op) => op.inverse(target),
+            DesignOperation::ReplaceComponent(op) => op.inverse(target),
+            DesignOperation::SplitExtrude(op) => op.inverse(target),
+            DesignOperation::JoinExtrude(op) => op.inverse(target),
+            DesignOperation::Pattern(op) => op.inverse(target),
+            DesignOperation::EditPattern(op) => op.inverse(target),
+            DesignOperation::SetPattern(op) => op.inverse(target),
+            DesignOperation::AddJoint(op) => op.inverse(target),
+            DesignOperation::RemoveJoint(op) => op.inverse(target),
+            DesignOperation::Batch(op) => op.inverse(target),
         }
     }
 
@@ -316,18 +574,25 @@ impl Operation for DesignOperation {
 use crate::component::ComponentData::*;
 
 #[wasm_bindgen]
-pub fn add_normal_instance(component: &Component) -> Result<DesignOperation, String> {
+pub fn add_normal_instance(
+    design: &mut DesignSpace,
+    component: &Component,
+) -> Result<DesignOperation, String> {
     match component.data {
         Extrude(_) | Panel(_) => Err("invalid component type: Extrude or Panel".to_string()),
         _ => Ok(DesignOperation::AddInstance(
-            AddInstance::default_component(component),
+            AddInstance::default_component(&mut design.ids, component),
         )),
     }
 }
 
 #[wasm_bindgen]
-pub fn add_extrude_instance(component: &Component, length: u32) -> Result<DesignOperation, String> {
-    if let Some(op) = AddInstance::extrude(component, length) {
+pub fn add_extrude_instance(
+    design: &mut DesignSpace,
+    component: &Component,
+    length: u32,
+) -> Result<DesignOperation, String> {
+    if let Some(op) = AddInstance::extrude(&mut design.ids, component, length) {
         Ok(DesignOperation::AddInstance(op))
     } else {
         Err("invalid component type: Not Extrude or Bad Length".to_string())
@@ -336,23 +601,48 @@ pub fn add_extrude_instance(component: &Component, length: u32) -> Result<Design
 
 #[wasm_bindgen]
 pub fn add_panel_instance(
+    design: &mut DesignSpace,
     component: &Component,
     x: u32,
     y: u32,
     thickness: u32,
 ) -> Result<DesignOperation, String> {
-    if let Some(op) = AddInstance::panel(component, x, y, thickness) {
+    if let Some(op) = AddInstance::panel(&mut design.ids, component, x, y, thickness) {
         Ok(DesignOperation::AddInstance(op))
     } else {
         Err("invalid component type: Not Extrude".to_string())
     }
 }
 
+/// add `connector` into a slot of `profile`, jointed to it
+#[wasm_bindgen]
+pub fn add_slot_connector_instance(
+    design: &mut DesignSpace,
+    profile: &Instance,
+    connector: &Component,
+) -> Result<DesignOperation, String> {
+    let component = design
+        .components
+        .components
+        .get(&profile.component_label)
+        .ok_or_else(|| format!("component not found: {}", profile.component_label))?;
+    check_slot_compatible(component, connector).map_err(|e| e.to_string())?;
+    let add = AddInstance::default_component(&mut design.ids, connector);
+    let joint = Joint::new(design.ids.next_id(), profile.id, add.instance.id);
+    Ok(DesignOperation::Batch(Batch {
+        operations: vec![
+            DesignOperation::AddInstance(add),
+            DesignOperation::AddJoint(AddJoint { joint }),
+        ],
+    }))
+}
+
 #[wasm_bindgen]
 pub fn remove_instance(instance: &Instance) -> DesignOperation {
     DesignOperation::RemoveInstance(RemoveInstance {
         id: instance.id,
         removed_instance: None,
+        removed_joints: Vec::new(),
     })
 }
 
@@ -374,6 +664,26 @@ pub fn extrude_post_process(
     }
 }
 
+#[wasm_bindgen]
+pub fn replace_component(
+    instance: &Instance,
+    component: &Component,
+) -> Result<DesignOperation, String> {
+    let config = instance.config.carry_over(component);
+    if let InstanceConfig::Extrude(_) = config {
+        if !config.is_extrude_config_valid(component) {
+            return Err("invalid config".into());
+        }
+    }
+    Ok(DesignOperation::ReplaceComponent(ReplaceComponent {
+        id: instance.id,
+        component_label: component.label.clone(),
+        component_type: ComponentType::from_data(&component.data),
+        config,
+        old_component: None,
+    }))
+}
+
 #[wasm_bindgen]
 pub fn extrude_add_length(
     instance: &Instance,
@@ -384,16 +694,42 @@ pub fn extrude_add_length(
     DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
         id: instance.id,
         dlength: d_length,
-        new_matrix: nalgebra::Isometry3::from_parts(
-            nalgebra::Translation3::new(tra.x, tra.y, tra.z),
-            nalgebra::UnitQuaternion::new_normalize(nalgebra::Quaternion::new(
-                quat.w, quat.i, quat.j, quat.k,
-            )),
-        ),
+        new_matrix: placement(&tra, &quat),
         old_matrix: None,
     })
 }
 
+/// set the length of an extrusion, the anchor stays where it is. The length is
+/// snapped to the component's step and clamped to its min/max.
+#[wasm_bindgen]
+pub fn extrude_set_length(
+    instance: &Instance,
+    component: &Component,
+    length: u32,
+    anchor: ExtrudeAnchor,
+) -> Result<DesignOperation, String> {
+    let (old_length, range) = match (&instance.config, &component.data) {
+        (InstanceConfig::Extrude(e), Extrude(data)) => (e.length, &data.post_process.length),
+        _ => return Err("invalid component type: Not Extrude".to_string()),
+    };
+    let dlength = range.snap(length) as i32 - old_length as i32;
+    // 型材沿局部 x 轴, 中心在原点
+    let shift = match anchor {
+        ExtrudeAnchor::Left => dlength as f64 / 2.,
+        ExtrudeAnchor::Right => -dlength as f64 / 2.,
+        ExtrudeAnchor::Center => 0.,
+    } / POSITION_LENGTH_UNIT;
+    let axis = instance.matrix.rotation * Vector3::x();
+    let mut new_matrix = instance.matrix;
+    new_matrix.translation.vector += axis * shift;
+    Ok(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+        id: instance.id,
+        dlength,
+        new_matrix,
+        old_matrix: None,
+    }))
+}
+
 #[wasm_bindgen]
 pub fn panel_add_size(
     instance: &Instance,
@@ -408,12 +744,7 @@ pub fn panel_add_size(
         dx,
         dy,
         dthickness,
-        new_matrix: nalgebra::Isometry3::from_parts(
-            nalgebra::Translation3::new(tra.x, tra.y, tra.z),
-            nalgebra::UnitQuaternion::new_normalize(nalgebra::Quaternion::new(
-                quat.w, quat.i, quat.j, quat.k,
-            )),
-        ),
+        new_matrix: placement(&tra, &quat),
         old_matrix: None,
     })
 }
@@ -422,21 +753,32 @@ pub fn panel_add_size(
 pub fn move_instance(instance: &Instance, tra: Translation, quat: Quaternion) -> DesignOperation {
     DesignOperation::MoveInstance(MoveInstance {
         id: instance.id,
-        new_matrix: nalgebra::Isometry3::from_parts(
-            nalgebra::Translation3::new(tra.x, tra.y, tra.z),
-            nalgebra::UnitQuaternion::new_normalize(nalgebra::Quaternion::new(
-                quat.w, quat.i, quat.j, quat.k,
-            )),
-        ),
+        new_matrix: placement(&tra, &quat),
         old_matrix: None,
     })
 }
 
+#[wasm_bindgen]
+pub fn add_joint(design: &mut DesignSpace, a: &Instance, b: &Instance) -> DesignOperation {
+    DesignOperation::AddJoint(AddJoint {
+        joint: Joint::new(design.ids.next_id(), a.id, b.id),
+    })
+}
+
+#[wasm_bindgen]
+pub fn remove_joint(joint: &Joint) -> DesignOperation {
+    DesignOperation::RemoveJoint(RemoveJoint {
+        id: joint.id,
+        removed_joint: None,
+    })
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
         component::{ComponentLib, ComponentType},
         instance::PanelConfig,
+        unit::LengthUnit,
     };
 
     use super::*;
@@ -447,7 +789,7 @@ mod test {
     fn test_add_normal_instance() {
         let lib = ComponentLib::default();
         let component = lib.components.get("Accessory-test").unwrap();
-        let op = add_normal_instance(component).unwrap();
+        let op = add_normal_instance(&mut DesignSpace::new(), component).unwrap();
         if let DesignOperation::AddInstance(op) = op {
             assert_ne!(op.instance.id, Uuid::new_v4());
             assert_eq!(op.instance.component_label, "Accessory-test");
@@ -463,9 +805,10 @@ mod test {
     fn test_add_extrude_instance() {
         let lib = ComponentLib::default();
         let component = lib.components.get("LCF8-4040").unwrap();
-        let op = add_extrude_instance(component, 100);
+        let mut design = DesignSpace::new();
+        let op = add_extrude_instance(&mut design, component, 100);
         assert!(op.is_err());
-        let op = add_extrude_instance(component, 10000).unwrap();
+        let op = add_extrude_instance(&mut design, component, 10000).unwrap();
         if let DesignOperation::AddInstance(op) = op {
             assert_ne!(op.instance.id, Uuid::new_v4());
             assert_eq!(op.instance.component_label, "LCF8-4040");
@@ -493,7 +836,8 @@ mod test {
     fn test_add_panel_instance() {
         let lib = ComponentLib::default();
         let component = lib.components.get("WoodenPanel-test").unwrap();
-        let op = add_panel_instance(component, 100000, 100000, 2000).unwrap();
+        let op =
+            add_panel_instance(&mut DesignSpace::new(), component, 100000, 100000, 2000).unwrap();
         if let DesignOperation::AddInstance(op) = op {
             assert_ne!(op.instance.id, Uuid::new_v4());
             assert_eq!(op.instance.component_label, "WoodenPanel-test");
@@ -534,7 +878,7 @@ mod test {
     fn test_extrude_post_process() {
         let lib = ComponentLib::default();
         let component = lib.components.get("LCF8-4040").unwrap();
-        let instance = Instance::default_extrude(component, 10000).unwrap();
+        let instance = Instance::default_extrude(Uuid::new_v4(), component, 10000).unwrap();
         let config = ExtrudeConfig {
             drill_left: true,
             drill_right: true,
@@ -588,6 +932,50 @@ mod test {
         }
     }
 
+    #[wasm_bindgen_test]
+    fn test_extrude_set_length() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let mut instance = Instance::default_extrude(Uuid::new_v4(), component, 100000).unwrap();
+        // extrusion along world y
+        instance.matrix = Isometry3::new(
+            nalgebra::Vector3::new(1.0, 0.0, 0.0),
+            nalgebra::Vector3::z() * std::f64::consts::FRAC_PI_2,
+        );
+        let end = |matrix: &Isometry3<f64>, length: f64, x: f64| {
+            matrix * nalgebra::Point3::new(x * length / 2., 0.0, 0.0)
+        };
+
+        for (anchor, x) in [
+            (ExtrudeAnchor::Left, -1.),
+            (ExtrudeAnchor::Right, 1.),
+            (ExtrudeAnchor::Center, 0.),
+        ] {
+            // 1500.3mm snaps to 1500.5mm
+            let op = extrude_set_length(&instance, component, 150030, anchor).unwrap();
+            if let DesignOperation::ExtrudeAddLength(op) = op {
+                assert_eq!(op.dlength, 50050);
+                let before = end(&instance.matrix, 1.0, x);
+                let after = end(&op.new_matrix, 1.5005, x);
+                assert!((before - after).norm() < 1e-9);
+            } else {
+                panic!("invalid operation type");
+            }
+        }
+
+        // clamped to the max length, also from the far end of the u32 range
+        for length in [500000, u32::MAX] {
+            let op = extrude_set_length(&instance, component, length, ExtrudeAnchor::Left).unwrap();
+            if let DesignOperation::ExtrudeAddLength(op) = op {
+                assert_eq!(op.dlength, 300000);
+            } else {
+                panic!("invalid operation type");
+            }
+        }
+        let panel = lib.components.get("WoodenPanel-test").unwrap();
+        assert!(extrude_set_length(&instance, panel, 100000, ExtrudeAnchor::Left).is_err());
+    }
+
     #[wasm_bindgen_test]
     fn test_panel_add_size() {
         let instance = Instance {
@@ -640,5 +1028,59 @@ mod test {
         } else {
             panic!("invalid operation type");
         }
+        // a position from js lands on the grid, not on the f32 next to it
+        let tra = Translation {
+            x: 0.1,
+            y: -2.5,
+            z: 0.,
+        };
+        if let DesignOperation::MoveInstance(op) =
+            move_instance(&instance, tra, Quaternion::identity())
+        {
+            assert_eq!(op.new_matrix, Isometry3::translation(0.1, -2.5, 0.));
+        } else {
+            panic!("invalid operation type");
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_far_positions_do_not_drift() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        design.add_component(component);
+        let mut ids = Vec::new();
+        // 12 km out, far beyond where f32 holds the grid, and one off the grid from an old log
+        for x in [12345.678905, 0.1000004] {
+            let mut op = add_extrude_instance(&mut design, component, 100000).unwrap();
+            if let DesignOperation::AddInstance(op) = &mut op {
+                op.instance.matrix = Isometry3::translation(x, 0., 0.);
+            }
+            ids.extend(op.instance_ids());
+            design.push(op).unwrap();
+        }
+        let far = design.instances[&ids[0]].clone();
+        let off_grid = design.instances[&ids[1]].matrix;
+
+        for _ in 0..100 {
+            let instance = design.instances[&ids[0]].clone();
+            let op = extrude_set_length(&instance, component, 100050, ExtrudeAnchor::Left).unwrap();
+            design.push(op).unwrap();
+            let instance = design.instances[&ids[0]].clone();
+            let op = extrude_set_length(&instance, component, 100000, ExtrudeAnchor::Left).unwrap();
+            design.push(op).unwrap();
+        }
+        assert_eq!(design.instances[&ids[0]], far);
+        // edits leave instances they do not touch as they are
+        assert_eq!(design.instances[&ids[1]].matrix, off_grid);
+        for _ in 0..200 {
+            design.pop();
+        }
+        assert_eq!(design.instances[&ids[0]], far);
+        assert_eq!(design.instances[&ids[1]].matrix, off_grid);
+        assert_eq!(
+            far.position(LengthUnit::Millimeter),
+            vec![12345678.905, 0., 0.]
+        );
     }
 }
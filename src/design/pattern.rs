@@ -0,0 +1,499 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use nalgebra::{Isometry3, Point3, Translation3, Unit, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use uuid::Uuid;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    instance::{Instance, POSITION_LENGTH_UNIT},
+    unit::snap_position,
+    Translation,
+};
+
+use super::{
+    id::parse_ids,
+    joint::Joint,
+    operation::{DesignOperation, Operation},
+    DesignSpace,
+};
+
+// 阵列方式, 数量包含源实例本身, 间距 0.01mm
+#[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum PatternKind {
+    Linear {
+        direction: Translation,
+        count: u32,
+        pitch: u32,
+    },
+    Grid {
+        x_direction: Translation,
+        x_count: u32,
+        x_pitch: u32,
+        y_direction: Translation,
+        y_count: u32,
+        y_pitch: u32,
+    },
+    // 绕过 origin 的 axis 旋转, angle 为相邻两份的夹角 rad
+    Circular {
+        origin: Translation,
+        axis: Translation,
+        count: u32,
+        angle: f32,
+    },
+}
+
+// 一个阵列最多的份数, 包含源实例
+const MAX_PATTERN_COUNT: u32 = 1000;
+
+fn direction(t: &Translation) -> Result<Unit<Vector3<f64>>, String> {
+    Unit::try_new(Vector3::new(t.x, t.y, t.z).cast(), f64::EPSILON)
+        .ok_or_else(|| "invalid pattern direction".to_string())
+}
+
+impl PatternKind {
+    /// placement of every copy relative to the sources, the sources themselves excluded
+    fn transforms(&self) -> Result<Vec<Isometry3<f64>>, String> {
+        let count = match self {
+            PatternKind::Linear { count, .. } | PatternKind::Circular { count, .. } => {
+                *count as u64
+            }
+            PatternKind::Grid {
+                x_count, y_count, ..
+            } => *x_count as u64 * *y_count as u64,
+        };
+        if count > MAX_PATTERN_COUNT as u64 {
+            return Err(format!(
+                "a pattern has at most {} instances",
+                MAX_PATTERN_COUNT
+            ));
+        }
+        let step = |d: &Translation, pitch: u32| {
+            direction(d).map(|d| d.into_inner() * (pitch as f64 / POSITION_LENGTH_UNIT))
+        };
+        let transforms: Vec<Isometry3<f64>> = match self {
+            PatternKind::Linear {
+                direction,
+                count,
+                pitch,
+            } => {
+                let step = step(direction, *pitch)?;
+                (1..*count)
+                    .map(|i| Translation3::from(step * i as f64).into())
+                    .collect()
+            }
+            PatternKind::Grid {
+                x_direction,
+                x_count,
+                x_pitch,
+                y_direction,
+                y_count,
+                y_pitch,
+            } => {
+                let x = step(x_direction, *x_pitch)?;
+                let y = step(y_direction, *y_pitch)?;
+                (0..*y_count)
+                    .flat_map(|j| (0..*x_count).map(move |i| (i, j)))
+                    .skip(1)
+                    .map(|(i, j)| Translation3::from(x * i as f64 + y * j as f64).into())
+                    .collect()
+            }
+            PatternKind::Circular {
+                origin,
+                axis,
+                count,
+                angle,
+            } => {
+                let axis = direction(axis)?;
+                let origin =
+                    Point3::new(origin.x, origin.y, origin.z).map(|v| snap_position(v as f64));
+                (1..*count)
+                    .map(|i| {
+                        Isometry3::rotation_wrt_point(
+                            UnitQuaternion::from_axis_angle(&axis, *angle as f64 * i as f64),
+                            origin,
+                        )
+                    })
+                    .collect()
+            }
+        };
+        if transforms.is_empty() {
+            return Err("a pattern needs at least one copy".to_string());
+        }
+        Ok(transforms)
+    }
+}
+
+// 阵列特征, 可修改数量与间距后重新生成副本
+#[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
+pub struct PatternFeature {
+    #[tsify(type = "string")]
+    pub id: Uuid,
+    #[tsify(type = "string[]")]
+    pub sources: Vec<Uuid>,
+    pub kind: PatternKind,
+    #[tsify(type = "string[]")]
+    pub copies: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PatternFeatures(pub Vec<PatternFeature>);
+
+/// copy instances by a pattern and remember it as a feature
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pattern {
+    pub(crate) feature: PatternFeature,
+    pub(crate) copies: Vec<Instance>,
+}
+
+impl Operation for Pattern {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        for copy in self.copies.iter() {
+            target.instances.insert(copy.id, copy.clone());
+        }
+        target
+            .patterns
+            .insert(self.feature.id, self.feature.clone());
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        for copy in self.copies.iter() {
+            target.instances.remove(&copy.id);
+        }
+        target.patterns.remove(&self.feature.id);
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+/// regenerate the copies of a pattern feature with another count or pitch
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditPattern {
+    pub(crate) feature: PatternFeature,
+    pub(crate) copies: Vec<Instance>,
+    pub(crate) old_copies: Vec<Uuid>,
+    // 修改前的特征, 删除的副本及其连接
+    pub(crate) old_feature: Option<(PatternFeature, Vec<Instance>, Vec<Joint>)>,
+}
+
+impl Operation for EditPattern {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        let removed: Vec<Instance> = self
+            .old_copies
+            .iter()
+            .filter_map(|id| target.instances.remove(id))
+            .collect();
+        let joints = removed
+            .iter()
+            .flat_map(|i| target.detach_joints(&i.id))
+            .collect();
+        for copy in self.copies.iter() {
+            target.instances.insert(copy.id, copy.clone());
+        }
+        if let Some(old) = target
+            .patterns
+            .insert(self.feature.id, self.feature.clone())
+        {
+            self.old_feature.replace((old, removed, joints));
+        }
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        if let Some((old, removed, joints)) = self.old_feature.take() {
+            for copy in self.copies.iter() {
+                target.instances.remove(&copy.id);
+            }
+            for instance in removed {
+                target.instances.insert(instance.id, instance);
+            }
+            target.restore_joints(joints);
+            target.patterns.insert(old.id, old);
+        }
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+/// replace or add a pattern feature without touching its instances, e.g. from a merge
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetPattern {
+    pub(crate) feature: PatternFeature,
+    pub(crate) old_feature: Option<Option<PatternFeature>>,
+}
+
+impl Operation for SetPattern {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        let old = target
+            .patterns
+            .insert(self.feature.id, self.feature.clone());
+        self.old_feature.replace(old);
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        match self.old_feature.take() {
+            Some(Some(old)) => {
+                target.patterns.insert(old.id, old);
+            }
+            Some(None) => {
+                target.patterns.remove(&self.feature.id);
+            }
+            None => {}
+        }
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+impl DesignSpace {
+    fn pattern_copies(
+        &mut self,
+        sources: &[Uuid],
+        kind: &PatternKind,
+    ) -> Result<Vec<Instance>, String> {
+        let sources = sources
+            .iter()
+            .map(|id| {
+                self.instances
+                    .get(id)
+                    .cloned()
+                    .ok_or_else(|| format!("instance not found: {}", id))
+            })
+            .collect::<Result<Vec<Instance>, String>>()?;
+        let mut copies = Vec::new();
+        for transform in kind.transforms()? {
+            for source in sources.iter() {
+                let mut copy = source.clone();
+                copy.id = self.ids.next_id();
+                copy.matrix = transform * source.matrix;
+                copies.push(copy);
+            }
+        }
+        Ok(copies)
+    }
+
+    pub(crate) fn pattern_features(&self) -> PatternFeatures {
+        let mut features: Vec<PatternFeature> = self.patterns.values().cloned().collect();
+        features.sort_by_key(|f| f.id);
+        PatternFeatures(features)
+    }
+}
+
+#[wasm_bindgen]
+pub fn pattern_instances(
+    design: &mut DesignSpace,
+    ids: Vec<String>,
+    kind: PatternKind,
+) -> Result<DesignOperation, String> {
+    let sources = parse_ids(&ids)?;
+    if sources.is_empty() {
+        return Err("nothing to pattern".to_string());
+    }
+    let copies = design.pattern_copies(&sources, &kind)?;
+    Ok(DesignOperation::Pattern(Pattern {
+        feature: PatternFeature {
+            id: design.ids.next_id(),
+            sources,
+            kind,
+            copies: copies.iter().map(|c| c.id).collect(),
+        },
+        copies,
+    }))
+}
+
+#[wasm_bindgen]
+pub fn edit_pattern(
+    design: &mut DesignSpace,
+    pattern: String,
+    kind: PatternKind,
+) -> Result<DesignOperation, String> {
+    let id = Uuid::parse_str(&pattern).map_err(|e| e.to_string())?;
+    let old = design
+        .patterns
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| format!("pattern not found: {}", pattern))?;
+    // 删除的源实例与副本不再属于阵列
+    let exists = |id: &Uuid| design.instances.contains_key(id);
+    let sources: Vec<Uuid> = old.sources.iter().copied().filter(exists).collect();
+    let old_copies: Vec<Uuid> = old.copies.iter().copied().filter(exists).collect();
+    if sources.is_empty() {
+        return Err(format!(
+            "every source of the pattern was deleted: {}",
+            pattern
+        ));
+    }
+    let copies = design.pattern_copies(&sources, &kind)?;
+    Ok(DesignOperation::EditPattern(EditPattern {
+        feature: PatternFeature {
+            id,
+            sources,
+            kind,
+            copies: copies.iter().map(|c| c.id).collect(),
+        },
+        copies,
+        old_copies,
+        old_feature: None,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::operation::{add_extrude_instance, remove_instance},
+    };
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn position(design: &DesignSpace, id: &Uuid) -> Vector3<f64> {
+        design.instances[id].matrix.translation.vector
+    }
+
+    #[wasm_bindgen_test]
+    fn pattern_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        design.use_sequential_ids(1);
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let op = add_extrude_instance(&mut design, bar, 100000).unwrap();
+            ids.push(op.instance_ids()[0].to_string());
+            design.push(op).unwrap();
+        }
+
+        // 3 shelves 300mm apart along z
+        let z = Translation {
+            x: 0.,
+            y: 0.,
+            z: 1.,
+        };
+        let linear = |count| PatternKind::Linear {
+            direction: z.clone(),
+            count,
+            pitch: 30000,
+        };
+        let op = pattern_instances(&mut design, ids.clone(), linear(3)).unwrap();
+        let changes = design.push(op).unwrap();
+        assert_eq!(changes.created.len(), 4);
+        assert_eq!(design.instances.len(), 6);
+        let feature = design.pattern_features().0[0].clone();
+        assert!((position(&design, &feature.copies[3]).z - 0.6).abs() < 1e-6);
+
+        // edit the count as a feature, one undo brings the old copies back
+        let op = edit_pattern(&mut design, feature.id.to_string(), linear(5)).unwrap();
+        let changes = design.push(op).unwrap();
+        assert_eq!((changes.created.len(), changes.deleted.len()), (8, 4));
+        assert_eq!(design.instances.len(), 10);
+        assert_eq!(design.pattern_features().0[0].kind, linear(5));
+        let replayed = DesignSpace::replay(design.export_log()).unwrap();
+        assert_eq!(replayed.pattern_features().0, design.pattern_features().0);
+        design.pop();
+        assert_eq!(design.instances.len(), 6);
+        assert_eq!(design.pattern_features().0[0], feature);
+        design.pop();
+        assert_eq!(design.instances.len(), 2);
+        assert!(design.pattern_features().0.is_empty());
+
+        // 2 x 3 grid
+        let x = Translation {
+            x: 1.,
+            y: 0.,
+            z: 0.,
+        };
+        let grid = PatternKind::Grid {
+            x_direction: x.clone(),
+            x_count: 2,
+            x_pitch: 50000,
+            y_direction: z.clone(),
+            y_count: 3,
+            y_pitch: 10000,
+        };
+        let op = pattern_instances(&mut design, ids[..1].to_vec(), grid).unwrap();
+        assert_eq!(op.instance_ids().len(), 5);
+        design.push(op).unwrap();
+        design.pop();
+
+        // 4 around the y axis through (1, 0, 0)
+        let circular = PatternKind::Circular {
+            origin: x,
+            axis: Translation {
+                x: 0.,
+                y: 1.,
+                z: 0.,
+            },
+            count: 4,
+            angle: std::f32::consts::FRAC_PI_2,
+        };
+        let op = pattern_instances(&mut design, ids[..1].to_vec(), circular).unwrap();
+        let copies = op.instance_ids();
+        design.push(op).unwrap();
+        assert!((position(&design, &copies[1]) - Vector3::new(2., 0., 0.)).norm() < 1e-6);
+        assert!(pattern_instances(&mut design, ids, linear(1)).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn edit_stale_pattern_test() {
+        let lib = ComponentLib::default();
+        let bar = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let op = add_extrude_instance(&mut design, bar, 100000).unwrap();
+            ids.push(op.instance_ids()[0].to_string());
+            design.push(op).unwrap();
+        }
+        let linear = |count| PatternKind::Linear {
+            direction: Translation {
+                x: 0.,
+                y: 0.,
+                z: 1.,
+            },
+            count,
+            pitch: 30000,
+        };
+        assert!(
+            pattern_instances(&mut design, ids.clone(), linear(MAX_PATTERN_COUNT + 1)).is_err()
+        );
+        let op = pattern_instances(&mut design, ids.clone(), linear(3)).unwrap();
+        design.push(op).unwrap();
+        let feature = design.pattern_features().0[0].clone();
+
+        // a deleted source and copy drop out of the pattern
+        for id in [feature.sources[1], feature.copies[0]] {
+            let op = remove_instance(&design.instances[&id]);
+            design.push(op).unwrap();
+        }
+        assert_eq!(design.instances.len(), 4);
+        let op = edit_pattern(&mut design, feature.id.to_string(), linear(4)).unwrap();
+        let changes = design.push(op).unwrap();
+        assert_eq!((changes.created.len(), changes.deleted.len()), (3, 3));
+        let edited = design.pattern_features().0[0].clone();
+        assert_eq!(edited.sources, feature.sources[..1].to_vec());
+        design.pop();
+        assert_eq!(design.pattern_features().0[0], feature);
+
+        let op = remove_instance(&design.instances[&feature.sources[0]]);
+        design.push(op).unwrap();
+        assert!(edit_pattern(&mut design, feature.id.to_string(), linear(4)).is_err());
+    }
+}
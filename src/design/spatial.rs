@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use nalgebra::{Point3, Vector3};
+use uuid::Uuid;
+
+use crate::instance::{Aabb, Obb};
+
+#[derive(Debug, Clone)]
+enum NodeData {
+    Leaf(Uuid, Obb),
+    Branch([usize; 2]),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    aabb: Aabb,
+    parent: Option<usize>,
+    data: NodeData,
+}
+
+/// dynamic bounding volume hierarchy over instance bounding boxes
+#[derive(Debug, Default)]
+pub(crate) struct SpatialIndex {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    leaves: HashMap<Uuid, usize>,
+}
+
+impl SpatialIndex {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    fn allocate(&mut self, node: Node) -> usize {
+        if let Some(index) = self.free.pop() {
+            self.nodes[index] = node;
+            index
+        } else {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        }
+    }
+
+    fn children(&self, index: usize) -> Option<[usize; 2]> {
+        match self.nodes[index].data {
+            NodeData::Branch(children) => Some(children),
+            NodeData::Leaf(..) => None,
+        }
+    }
+
+    /// insert or move the leaf of `id`
+    pub(crate) fn update(&mut self, id: Uuid, obb: Obb) {
+        if let Some(&leaf) = self.leaves.get(&id) {
+            if let NodeData::Leaf(_, old) = &self.nodes[leaf].data {
+                if *old == obb {
+                    return;
+                }
+            }
+            self.remove(&id);
+        }
+        self.insert(id, obb);
+    }
+
+    fn insert(&mut self, id: Uuid, obb: Obb) {
+        let aabb = obb.aabb();
+        let leaf = self.allocate(Node {
+            aabb,
+            parent: None,
+            data: NodeData::Leaf(id, obb),
+        });
+        self.leaves.insert(id, leaf);
+
+        let Some(mut sibling) = self.root else {
+            self.root = Some(leaf);
+            return;
+        };
+
+        // descend to the sibling that grows the tree the least
+        while let Some([c1, c2]) = self.children(sibling) {
+            let area = self.nodes[sibling].aabb.surface_area();
+            let combined = self.nodes[sibling].aabb.merge(&aabb).surface_area();
+            let cost = 2. * combined;
+            let inheritance = 2. * (combined - area);
+            let child_cost = |c: usize| {
+                let merged = self.nodes[c].aabb.merge(&aabb).surface_area();
+                match self.nodes[c].data {
+                    NodeData::Leaf(..) => merged + inheritance,
+                    NodeData::Branch(_) => merged - self.nodes[c].aabb.surface_area() + inheritance,
+                }
+            };
+            let (cost1, cost2) = (child_cost(c1), child_cost(c2));
+            if cost < cost1 && cost < cost2 {
+                break;
+            }
+            sibling = if cost1 < cost2 { c1 } else { c2 };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate(Node {
+            aabb: self.nodes[sibling].aabb.merge(&aabb),
+            parent: old_parent,
+            data: NodeData::Branch([sibling, leaf]),
+        });
+        self.nodes[sibling].parent = Some(parent);
+        self.nodes[leaf].parent = Some(parent);
+        match old_parent {
+            Some(old_parent) => {
+                self.replace_child(old_parent, sibling, parent);
+                self.refit(Some(old_parent));
+            }
+            None => self.root = Some(parent),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: &Uuid) {
+        let Some(leaf) = self.leaves.remove(id) else {
+            return;
+        };
+        self.free.push(leaf);
+        let Some(parent) = self.nodes[leaf].parent else {
+            self.root = None;
+            return;
+        };
+        let [c1, c2] = self.children(parent).unwrap();
+        let sibling = if c1 == leaf { c2 } else { c1 };
+        let grand_parent = self.nodes[parent].parent;
+        self.free.push(parent);
+        self.nodes[sibling].parent = grand_parent;
+        match grand_parent {
+            Some(grand_parent) => {
+                self.replace_child(grand_parent, parent, sibling);
+                self.refit(Some(grand_parent));
+            }
+            None => self.root = Some(sibling),
+        }
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let NodeData::Branch(children) = &mut self.nodes[parent].data {
+            for c in children.iter_mut() {
+                if *c == old {
+                    *c = new;
+                }
+            }
+        }
+    }
+
+    fn refit(&mut self, mut index: Option<usize>) {
+        while let Some(i) = index {
+            let [c1, c2] = self.children(i).unwrap();
+            self.nodes[i].aabb = self.nodes[c1].aabb.merge(&self.nodes[c2].aabb);
+            index = self.nodes[i].parent;
+        }
+    }
+
+    /// visit leaves whose branch boxes pass `filter`
+    fn visit(&self, filter: impl Fn(&Aabb) -> bool, mut visit: impl FnMut(&Uuid, &Obb)) {
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !filter(&node.aabb) {
+                continue;
+            }
+            match &node.data {
+                NodeData::Leaf(id, obb) => visit(id, obb),
+                NodeData::Branch(children) => stack.extend(children),
+            }
+        }
+    }
+
+    /// nearest instance hit by the ray and its distance
+    pub(crate) fn ray_pick(&self, origin: &Point3<f32>, dir: &Vector3<f32>) -> Option<(Uuid, f32)> {
+        let mut nearest: Option<(Uuid, f32)> = None;
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let Some(t) = node.aabb.ray_distance(origin, dir) else {
+                continue;
+            };
+            if nearest.is_some_and(|(_, best)| t > best) {
+                continue;
+            }
+            match &node.data {
+                NodeData::Leaf(id, obb) => {
+                    if let Some(t) = obb.ray_distance(origin, dir) {
+                        if !nearest.is_some_and(|(_, best)| t >= best) {
+                            nearest = Some((*id, t));
+                        }
+                    }
+                }
+                NodeData::Branch(children) => stack.extend(children),
+            }
+        }
+        nearest
+    }
+
+    /// instances whose bounding boxes intersect `aabb`
+    pub(crate) fn select_box(&self, aabb: &Aabb) -> Vec<Uuid> {
+        let mut ids = Vec::new();
+        self.visit(
+            |b| b.intersects(aabb),
+            |id, obb| {
+                if obb.aabb().intersects(aabb) {
+                    ids.push(*id);
+                }
+            },
+        );
+        ids.sort();
+        ids
+    }
+
+    /// instances inside or crossing a frustum given by planes `normal · p + constant >= 0`
+    pub(crate) fn select_frustum(&self, planes: &[(Vector3<f32>, f32)]) -> Vec<Uuid> {
+        let inside = |b: &Aabb| !planes.iter().any(|(n, c)| b.outside_plane(n, *c));
+        let mut ids = Vec::new();
+        self.visit(inside, |id, obb| {
+            if inside(&obb.aabb()) {
+                ids.push(*id);
+            }
+        });
+        ids.sort();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::{
+            operation::{move_instance, AddInstance, DesignOperation},
+            DesignSpace,
+        },
+        Quaternion, Translation,
+    };
+    use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn bar(x: f32, y: f32, z: f32) -> Obb {
+        Obb {
+            matrix: Isometry3::translation(x, y, z),
+            half_extents: Vector3::new(0.5, 0.02, 0.02),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn spatial_index_test() {
+        let mut index = SpatialIndex::new();
+        let ids: Vec<Uuid> = (0..20).map(|_| Uuid::new_v4()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            index.update(*id, bar(0.0, i as f32 * 0.1, 0.0));
+        }
+        assert_eq!(index.leaves.len(), 20);
+
+        // the ray hits the closest bar
+        let (hit, t) = index
+            .ray_pick(&Point3::new(0.0, -1.0, 0.0), &Vector3::y())
+            .unwrap();
+        assert_eq!(hit, ids[0]);
+        assert!((t - 0.98).abs() < 1e-5);
+
+        let selected = index.select_box(&Aabb::new(
+            Point3::new(-1.0, 0.45, -1.0),
+            Point3::new(1.0, 0.75, 1.0),
+        ));
+        let mut expected = ids[5..8].to_vec();
+        expected.sort();
+        assert_eq!(selected, expected);
+
+        // y >= 1.75 and y <= 1.85
+        let selected = index.select_frustum(&[(Vector3::y(), -1.75), (-Vector3::y(), 1.85)]);
+        assert_eq!(selected, vec![ids[18]]);
+
+        // removing and moving leaves keeps the tree consistent
+        index.remove(&ids[0]);
+        index.update(
+            ids[1],
+            Obb {
+                matrix: Isometry3::from_parts(
+                    Translation3::new(5.0, 0.0, 0.0),
+                    UnitQuaternion::identity(),
+                ),
+                half_extents: Vector3::new(0.5, 0.02, 0.02),
+            },
+        );
+        assert_eq!(index.leaves.len(), 19);
+        let (hit, _) = index
+            .ray_pick(&Point3::new(0.0, -1.0, 0.0), &Vector3::y())
+            .unwrap();
+        assert_eq!(hit, ids[2]);
+        let (hit, _) = index
+            .ray_pick(&Point3::new(5.0, -1.0, 0.0), &Vector3::y())
+            .unwrap();
+        assert_eq!(hit, ids[1]);
+        for id in ids.iter() {
+            index.remove(id);
+        }
+        assert_eq!(index.leaves.len(), 0);
+        assert!(index
+            .ray_pick(&Point3::new(0.0, -1.0, 0.0), &Vector3::y())
+            .is_none());
+    }
+
+    fn pick_down(design: &DesignSpace) -> Option<String> {
+        design.pick(
+            Translation {
+                x: 0.,
+                y: 2.,
+                z: 0.,
+            },
+            Translation {
+                x: 0.,
+                y: -1.,
+                z: 0.,
+            },
+        )
+    }
+
+    #[wasm_bindgen_test]
+    fn design_space_pick_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        let add = AddInstance::extrude(&mut design.ids, component, 100000).unwrap();
+        let instance = add.instance.clone();
+        design.push(DesignOperation::AddInstance(add)).unwrap();
+
+        // not indexed until the component is known
+        assert_eq!(pick_down(&design), None);
+        design.add_component(component);
+        assert_eq!(pick_down(&design), Some(instance.id()));
+
+        design
+            .push(move_instance(
+                &instance,
+                Translation {
+                    x: 0.,
+                    y: 0.,
+                    z: 1.,
+                },
+                Quaternion::identity(),
+            ))
+            .unwrap();
+        assert_eq!(pick_down(&design), None);
+        design.pop();
+        assert_eq!(pick_down(&design), Some(instance.id()));
+        design.pop();
+        assert_eq!(pick_down(&design), None);
+    }
+}
@@ -0,0 +1,478 @@
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    component::Component,
+    instance::{ExtrudeConfig, Instance, InstanceConfig, WrenchHole, POSITION_LENGTH_UNIT},
+};
+
+use super::{
+    joint::Joint,
+    operation::{DesignOperation, Operation},
+    DesignSpace,
+};
+
+// 位置误差 m, 与长度精度 0.01mm 一致
+const TOLERANCE: f64 = 1.0 / POSITION_LENGTH_UNIT;
+
+/// cut one extrusion into two, `left` keeps the id of the cut instance. Joints at the
+/// right end move to `right`.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitExtrude {
+    pub(crate) left: Instance,
+    pub(crate) right: Instance,
+    pub(crate) old_instance: Option<Instance>,
+    pub(crate) moved_joints: Vec<Uuid>, // 移到 right 的连接
+}
+
+impl SplitExtrude {
+    fn move_joints(&self, target: &mut DesignSpace, from: Uuid, to: Uuid) {
+        for id in self.moved_joints.iter() {
+            if let Some(joint) = target.joints.get_mut(id) {
+                for end in [&mut joint.a, &mut joint.b] {
+                    if *end == from {
+                        *end = to;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Operation for SplitExtrude {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        if let Some(instance) = target.instances.get_mut(&self.left.id) {
+            self.old_instance
+                .replace(std::mem::replace(instance, self.left.clone()));
+            target.instances.insert(self.right.id, self.right.clone());
+            self.move_joints(target, self.left.id, self.right.id);
+        }
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        if let Some(old_instance) = self.old_instance.take() {
+            self.move_joints(target, self.right.id, self.left.id);
+            target.instances.remove(&self.right.id);
+            target.instances.insert(old_instance.id, old_instance);
+        }
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+/// merge `other` into `instance.id`, `instance` is the joined extrusion. Joints of `other`
+/// move to the joined extrusion, a joint between the two pieces is dropped.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinExtrude {
+    pub(crate) instance: Instance,
+    pub(crate) other: Uuid,
+    pub(crate) old_instances: Option<(Instance, Instance)>,
+    pub(crate) old_joints: Vec<Joint>, // 合并前 other 上的连接
+}
+
+impl JoinExtrude {
+    /// whether `instance` is what `a` and `b` join into, for operations from js or a log
+    pub(crate) fn is_join_of(&self, a: &Instance, b: &Instance, component: &Component) -> bool {
+        match joined(a, b, component) {
+            Ok(expected) => {
+                let (m, n) = (&expected.matrix, &self.instance.matrix);
+                expected.id == self.instance.id
+                    && b.id == self.other
+                    && expected.component_label == self.instance.component_label
+                    && expected.config == self.instance.config
+                    && (m.translation.vector - n.translation.vector).norm() <= TOLERANCE
+                    && m.rotation.angle_to(&n.rotation) < 1e-4
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl Operation for JoinExtrude {
+    type Target = DesignSpace;
+
+    fn operate(&mut self, target: &mut Self::Target) {
+        if let Some(other) = target.instances.remove(&self.other) {
+            if let Some(instance) = target.instances.get_mut(&self.instance.id) {
+                let old = std::mem::replace(instance, self.instance.clone());
+                self.old_instances.replace((old, other));
+                self.old_joints = target.detach_joints(&self.other);
+                for joint in self.old_joints.iter() {
+                    if joint.connects(&self.instance.id, &self.other) {
+                        continue;
+                    }
+                    let mut joint = joint.clone();
+                    for end in [&mut joint.a, &mut joint.b] {
+                        if *end == self.other {
+                            *end = self.instance.id;
+                        }
+                    }
+                    target.joints.insert(joint.id, joint);
+                }
+            } else {
+                target.instances.insert(other.id, other);
+            }
+        }
+    }
+
+    fn inverse(&mut self, target: &mut Self::Target) {
+        if let Some((instance, other)) = self.old_instances.take() {
+            target.instances.insert(instance.id, instance);
+            target.instances.insert(other.id, other);
+            target.restore_joints(std::mem::take(&mut self.old_joints));
+        }
+    }
+
+    fn compress(&mut self, _target: &Self) -> bool {
+        false
+    }
+}
+
+// 一端的加工: 钻孔, 扳手孔, 沉头孔
+type EndMachining = (bool, Option<WrenchHole>, u8);
+
+fn left_end(c: &ExtrudeConfig) -> EndMachining {
+    (c.drill_left, c.wrench_hole_left, c.counterbore_left)
+}
+
+fn right_end(c: &ExtrudeConfig) -> EndMachining {
+    (c.drill_right, c.wrench_hole_right, c.counterbore_right)
+}
+
+/// an extrusion config of `length` with the given end machining, bevel cut is not per end
+fn with_ends(
+    c: &ExtrudeConfig,
+    length: u32,
+    left: EndMachining,
+    right: EndMachining,
+) -> InstanceConfig {
+    InstanceConfig::Extrude(ExtrudeConfig {
+        drill_left: left.0,
+        drill_right: right.0,
+        bevel_cut: c.bevel_cut,
+        wrench_hole_left: left.1,
+        wrench_hole_right: right.1,
+        counterbore_left: left.2,
+        counterbore_right: right.2,
+        length,
+    })
+}
+
+fn extrude_config(instance: &Instance) -> Result<&ExtrudeConfig, String> {
+    match &instance.config {
+        InstanceConfig::Extrude(e) => Ok(e),
+        _ => Err("invalid component type: Not Extrude".to_string()),
+    }
+}
+
+/// the instance moved by `offset` along its extrusion axis, offset in 0.01mm
+fn shifted(instance: &Instance, offset: f64, config: InstanceConfig) -> Instance {
+    let mut instance = instance.clone();
+    let axis = instance.matrix.rotation * Vector3::x();
+    instance.matrix.translation.vector += axis * (offset / POSITION_LENGTH_UNIT);
+    instance.config = config;
+    instance
+}
+
+/// joints of `instance` whose other part sits past `position` from its left end, in 0.01mm
+fn joints_past(design: &DesignSpace, instance: &Instance, position: u32) -> Vec<Uuid> {
+    let e = match extrude_config(instance) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+    let axis = instance.matrix.rotation * Vector3::x();
+    // 另一零件原点在型材轴线上的投影, 从左端量起
+    let cut = (position as f64 - e.length as f64 / 2.) / POSITION_LENGTH_UNIT;
+    let mut joints: Vec<Uuid> = design
+        .joints
+        .values()
+        .filter_map(|j| {
+            let other = match (j.a == instance.id, j.b == instance.id) {
+                (true, _) => &j.b,
+                (_, true) => &j.a,
+                _ => return None,
+            };
+            let other = design.instances.get(other)?;
+            let d = other.matrix.translation.vector - instance.matrix.translation.vector;
+            (d.dot(&axis) > cut).then_some(j.id)
+        })
+        .collect();
+    joints.sort();
+    joints
+}
+
+/// cut an extrusion at `position` from its left end (local -x), in 0.01mm. Machining of
+/// each end stays with the piece that keeps that end, the cut ends are plain. Joints move
+/// with the piece their other part sits on.
+#[wasm_bindgen]
+pub fn split_extrude(
+    design: &mut DesignSpace,
+    instance: &Instance,
+    component: &Component,
+    position: u32,
+) -> Result<DesignOperation, String> {
+    let e = extrude_config(instance)?;
+    if position == 0 || position >= e.length {
+        return Err(format!("invalid split position: {}", position));
+    }
+    let plain = (false, None, 0);
+    let left = shifted(
+        instance,
+        (position as f64 - e.length as f64) / 2.,
+        with_ends(e, position, left_end(e), plain),
+    );
+    let mut right = shifted(
+        instance,
+        position as f64 / 2.,
+        with_ends(e, e.length - position, plain, right_end(e)),
+    );
+    if !left.config.is_extrude_config_valid(component)
+        || !right.config.is_extrude_config_valid(component)
+    {
+        return Err("invalid config".into());
+    }
+    right.id = design.ids.next_id();
+    Ok(DesignOperation::SplitExtrude(SplitExtrude {
+        left,
+        right,
+        old_instance: None,
+        moved_joints: joints_past(design, instance, position),
+    }))
+}
+
+/// the extrusion two collinear extrusions of the same component touching end to end join into
+fn joined(a: &Instance, b: &Instance, component: &Component) -> Result<Instance, String> {
+    let (ea, eb) = (extrude_config(a)?, extrude_config(b)?);
+    if a.id == b.id || a.component_label != b.component_label {
+        return Err("can only join two extrusions of the same component".into());
+    }
+    let axis = a.matrix.rotation * Vector3::x();
+    let axis_b = b.matrix.rotation * Vector3::x();
+    let d = b.matrix.translation.vector - a.matrix.translation.vector;
+    let along = d.dot(&axis);
+    let (la, lb) = (ea.length as f64, eb.length as f64);
+    if axis.dot(&axis_b).abs() < 1. - 1e-4
+        || (d - axis * along).norm() > TOLERANCE
+        || (along.abs() - (la + lb) / 2. / POSITION_LENGTH_UNIT).abs() > TOLERANCE
+    {
+        return Err("extrusions are not collinear and touching".into());
+    }
+
+    // b 的远端加工, 方向相反时左右互换
+    let same_direction = axis.dot(&axis_b) > 0.;
+    let (b_left, b_right) = if same_direction {
+        (left_end(eb), right_end(eb))
+    } else {
+        (right_end(eb), left_end(eb))
+    };
+    let length = ea.length + eb.length;
+    let mut config = ea.clone();
+    config.bevel_cut = ea.bevel_cut.or(eb.bevel_cut);
+    let (config, offset) = if along > 0. {
+        (with_ends(&config, length, left_end(ea), b_right), lb / 2.)
+    } else {
+        (with_ends(&config, length, b_left, right_end(ea)), -lb / 2.)
+    };
+    let instance = shifted(a, offset, config);
+    if !instance.config.is_extrude_config_valid(component) {
+        return Err("invalid config".into());
+    }
+    Ok(instance)
+}
+
+/// join two collinear extrusions of the same component that touch end to end,
+/// the joined extrusion keeps the id and orientation of `a`
+#[wasm_bindgen]
+pub fn join_extrude(
+    a: &Instance,
+    b: &Instance,
+    component: &Component,
+) -> Result<DesignOperation, String> {
+    Ok(DesignOperation::JoinExtrude(JoinExtrude {
+        instance: joined(a, b, component)?,
+        other: b.id,
+        old_instances: None,
+        old_joints: Vec::new(),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::ComponentLib,
+        design::{
+            operation::{add_extrude_instance, add_joint, AddInstance},
+            validate::OperationError,
+        },
+    };
+    use nalgebra::{Isometry3, Point3};
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn end(instance: &Instance, x: f64) -> Point3<f64> {
+        let length = extrude_config(instance).unwrap().length as f64 / POSITION_LENGTH_UNIT;
+        instance.matrix * Point3::new(x * length / 2., 0.0, 0.0)
+    }
+
+    #[wasm_bindgen_test]
+    fn split_join_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        design.add_component(component);
+        let mut op = add_extrude_instance(&mut design, component, 300000).unwrap();
+        if let DesignOperation::AddInstance(op) = &mut op {
+            op.instance.matrix = Isometry3::new(
+                Vector3::new(0.0, 0.0, 1.0),
+                Vector3::z() * std::f64::consts::FRAC_PI_2,
+            );
+            if let InstanceConfig::Extrude(e) = &mut op.instance.config {
+                e.drill_left = true;
+                e.counterbore_right = 2;
+            }
+        }
+        let id = op.instance_ids()[0];
+        design.push(op).unwrap();
+        let before = design.instances[&id].clone();
+
+        let op = split_extrude(&mut design, &before, component, 100000).unwrap();
+        let new_id = op.instance_ids()[1];
+        design.push(op).unwrap();
+        let (left, right) = (&design.instances[&id], &design.instances[&new_id]);
+        let (l, r) = (
+            extrude_config(left).unwrap(),
+            extrude_config(right).unwrap(),
+        );
+        assert_eq!((l.length, r.length), (100000, 200000));
+        assert!(l.drill_left && !l.drill_right && l.counterbore_right == 0);
+        assert!(!r.drill_left && r.counterbore_right == 2);
+        assert!((end(left, -1.) - end(&before, -1.)).norm() < TOLERANCE);
+        assert!((end(left, 1.) - end(right, -1.)).norm() < TOLERANCE);
+        assert!((end(right, 1.) - end(&before, 1.)).norm() < TOLERANCE);
+
+        // joining from either side restores the original member
+        for (a, b) in [(id, new_id), (new_id, id)] {
+            let op = join_extrude(&design.instances[&a], &design.instances[&b], component).unwrap();
+            design.push(op).unwrap();
+            assert_eq!(design.instances.len(), 1);
+            let joined = &design.instances[&a];
+            assert_eq!(joined.config, before.config);
+            assert!((end(joined, -1.) - end(&before, -1.)).norm() < TOLERANCE);
+            assert!((end(joined, 1.) - end(&before, 1.)).norm() < TOLERANCE);
+            design.pop();
+            assert_eq!(design.instances.len(), 2);
+        }
+
+        // joints of the absorbed piece move to the joined member
+        let op = add_extrude_instance(&mut design, component, 100000).unwrap();
+        let post = op.instance_ids()[0];
+        design.push(op).unwrap();
+        for (a, b) in [(new_id, post), (id, new_id)] {
+            let (a, b) = (design.instances[&a].clone(), design.instances[&b].clone());
+            let op = add_joint(&mut design, &a, &b);
+            design.push(op).unwrap();
+        }
+        let op = join_extrude(
+            &design.instances[&id],
+            &design.instances[&new_id],
+            component,
+        )
+        .unwrap();
+        design.push(op).unwrap();
+        let joints = design.get_joints();
+        assert_eq!(joints.len(), 1);
+        assert!(joints[0].connects(&id, &post));
+        design.pop();
+        assert_eq!(design.joints.len(), 2);
+        assert!(design.joints.values().any(|j| j.connects(&new_id, &post)));
+        for _ in 0..3 {
+            design.pop();
+        }
+
+        design.pop();
+        assert_eq!(design.instances.len(), 1);
+        assert_eq!(design.instances[&id], before);
+
+        // out of range pieces and members apart are refused
+        assert!(split_extrude(&mut design, &before, component, 1000).is_err());
+        let mut apart = before.clone();
+        apart.id = Uuid::new_v4();
+        apart.matrix.translation.vector.z += 0.1;
+        assert!(join_extrude(&before, &apart, component).is_err());
+
+        // a join from js or a log is checked the same way
+        design
+            .push(DesignOperation::AddInstance(AddInstance {
+                instance: apart.clone(),
+            }))
+            .unwrap();
+        let mut forged = |other: Uuid| {
+            let mut instance = before.clone();
+            if let InstanceConfig::Extrude(e) = &mut instance.config {
+                e.length *= 2;
+            }
+            design.push(DesignOperation::JoinExtrude(JoinExtrude {
+                instance,
+                other,
+                old_instances: None,
+                old_joints: Vec::new(),
+            }))
+        };
+        let mismatch = Err(OperationError::ConfigMismatch(id.to_string()));
+        assert_eq!(forged(apart.id), mismatch);
+        assert_eq!(forged(id), mismatch);
+        assert_eq!(design.instances.len(), 2);
+    }
+
+    #[wasm_bindgen_test]
+    fn split_joint_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4040").unwrap();
+        let mut design = DesignSpace::new();
+        design.use_sequential_ids(1);
+        let add = |design: &mut DesignSpace, x: f64| {
+            let mut op = add_extrude_instance(design, component, 300000).unwrap();
+            if let DesignOperation::AddInstance(op) = &mut op {
+                op.instance.matrix = Isometry3::translation(x, 0.0, 0.0);
+            }
+            let instance = op.instance_ids()[0];
+            design.push(op).unwrap();
+            design.instances[&instance].clone()
+        };
+        let member = add(&mut design, 0.0);
+        // posts near each end of the member
+        let left_post = add(&mut design, -0.1);
+        let right_post = add(&mut design, 0.1);
+        let mut joints = Vec::new();
+        for post in [&left_post, &right_post] {
+            let op = add_joint(&mut design, &member, post);
+            joints.extend(op.joint_ids());
+            design.push(op).unwrap();
+        }
+
+        // a refused split takes no id
+        let ids = design.ids.clone();
+        assert!(split_extrude(&mut design, &member, component, 1000).is_err());
+        assert_eq!(design.ids, ids);
+
+        let op = split_extrude(&mut design, &member, component, 150000).unwrap();
+        let right = op.instance_ids()[1];
+        design.push(op).unwrap();
+        assert!(design.joints[&joints[0]].connects(&member.id, &left_post.id));
+        assert!(design.joints[&joints[1]].connects(&right, &right_post.id));
+
+        design.pop();
+        assert!(design.joints[&joints[1]].connects(&member.id, &right_post.id));
+        design.repush();
+        assert!(design.joints[&joints[1]].connects(&right, &right_post.id));
+    }
+}
@@ -0,0 +1,221 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use nalgebra::{Point3, Vector3};
+use uuid::Uuid;
+
+use crate::{
+    assembly_node::{InnerScrewFace, OuterScrewFace, ScrewWarning},
+    component::ComponentData,
+    instance::{Instance, InstanceConfig, LENGTH_UNIT},
+};
+
+use super::DesignSpace;
+
+// 螺纹连接的旋合问题
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq)]
+pub struct ThreadWarning {
+    pub joint: String,
+    pub bolt: String, // 外螺纹实例
+    pub warning: ScrewWarning,
+}
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ThreadWarnings(pub Vec<ThreadWarning>);
+
+impl Instance {
+    fn world(&self, x: f32) -> Vector3<f32> {
+        (self.isometry() * Point3::new(x, 0., 0.)).coords
+    }
+
+    /// threaded holes of the instance in world space, tapped extrusion ends and nuts
+    fn inner_screw_faces(&self, data: &ComponentData) -> Vec<InnerScrewFace> {
+        if let (InstanceConfig::Extrude(e), Some((screw_type, depth))) =
+            (&self.config, data.end_tap())
+        {
+            // 端面中心孔, 从端面向内攻牙
+            let half = e.length as f32 / LENGTH_UNIT / 2.;
+            let depth = depth as f32 / LENGTH_UNIT;
+            return [(e.drill_left, -1.), (e.drill_right, 1.)]
+                .iter()
+                .filter(|(drill, _)| *drill)
+                .filter_map(|(_, side)| {
+                    InnerScrewFace::new(
+                        screw_type,
+                        self.world(side * half),
+                        self.world(side * (half - depth)),
+                    )
+                })
+                .collect();
+        }
+        // 螺母中心在原点, 螺纹沿局部 x 贯通
+        data.nut_thread()
+            .and_then(|(screw_type, height)| {
+                let half = height as f32 / LENGTH_UNIT / 2.;
+                InnerScrewFace::through_hole(screw_type, self.world(-half), self.world(half))
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// the bolt thread in world space, from under the head at the origin along local +x
+    fn outer_screw_face(&self, data: &ComponentData) -> Option<OuterScrewFace> {
+        let (screw_type, length) = data.bolt_thread()?;
+        OuterScrewFace::new(
+            screw_type,
+            self.world(0.),
+            self.world(length as f32 / LENGTH_UNIT),
+        )
+    }
+}
+
+impl DesignSpace {
+    /// engagement warnings of bolts jointed to a threaded hole, the hole the bolt
+    /// engages is preferred, then one on its axis
+    pub(crate) fn find_thread_warnings(&self) -> Vec<ThreadWarning> {
+        let data = |i: &Instance| {
+            self.components
+                .components
+                .get(&i.component_label)
+                .map(|c| &c.data)
+        };
+        let mut joints: Vec<(&Uuid, Uuid, Uuid)> =
+            self.joints.values().map(|j| (&j.id, j.a, j.b)).collect();
+        joints.sort();
+
+        let mut warnings = Vec::new();
+        for (joint, a, b) in joints {
+            for (bolt, other) in [(a, b), (b, a)] {
+                let (Some(bolt), Some(other)) =
+                    (self.instances.get(&bolt), self.instances.get(&other))
+                else {
+                    continue;
+                };
+                let (Some(bolt_data), Some(other_data)) = (data(bolt), data(other)) else {
+                    continue;
+                };
+                let Some(outer) = bolt.outer_screw_face(bolt_data) else {
+                    continue;
+                };
+                let faces = other.inner_screw_faces(other_data);
+                let inner = faces
+                    .iter()
+                    .find(|f| outer.is_match(f))
+                    .or_else(|| faces.iter().find(|f| f.is_coaxial(&outer)))
+                    .or_else(|| faces.first());
+                let Some(inner) = inner else {
+                    continue;
+                };
+                warnings.extend(outer.check_engagement(inner).into_iter().map(|warning| {
+                    ThreadWarning {
+                        joint: joint.to_string(),
+                        bolt: bolt.id.to_string(),
+                        warning,
+                    }
+                }));
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::test_util::bolt_lib,
+        design::operation::{
+            add_joint, replace_component, AddInstance, DesignOperation, MoveInstance,
+            PostProcessInstance,
+        },
+    };
+    use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn thread_warning_test() {
+        let lib = bolt_lib();
+        let mut design = DesignSpace::new();
+        for label in ["LCF8-4040", "Bolt-M8-12", "Bolt-M8-30"] {
+            design.add_component(&lib.components[label]);
+        }
+        let profile =
+            AddInstance::extrude(&mut design.ids, &lib.components["LCF8-4040"], 100000).unwrap();
+        let mut profile = profile.instance.clone();
+        design
+            .push(DesignOperation::AddInstance(AddInstance {
+                instance: profile.clone(),
+            }))
+            .unwrap();
+        // M8 tapped 25mm deep into the right end
+        let InstanceConfig::Extrude(mut config) = profile.config.clone() else {
+            unreachable!()
+        };
+        config.drill_right = true;
+        design
+            .push(DesignOperation::PostProcessInstance(PostProcessInstance {
+                id: profile.id,
+                config: InstanceConfig::Extrude(config),
+                config_cache: None,
+            }))
+            .unwrap();
+        profile = design.instances[&profile.id].clone();
+
+        let bolt = AddInstance::default_component(&mut design.ids, &lib.components["Bolt-M8-30"]);
+        let bolt_id = bolt.instance.id;
+        design.push(DesignOperation::AddInstance(bolt)).unwrap();
+        // the head sits on a 3mm plate over the right end, screwing in along -x
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id: bolt_id,
+                new_matrix: Isometry3::from_parts(
+                    Translation3::new(0.503, 0.0, 0.0),
+                    UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f64::consts::PI),
+                ),
+                old_matrix: None,
+            }))
+            .unwrap();
+        let bolt = design.instances[&bolt_id].clone();
+        let joint = add_joint(&mut design, &bolt, &profile);
+        design.push(joint).unwrap();
+
+        // 27mm of thread in a 25mm hole
+        match design.thread_warnings().0.as_slice() {
+            [ThreadWarning {
+                warning: ScrewWarning::Protruding { protrusion },
+                ..
+            }] => assert!((protrusion - 0.002).abs() < 1e-5),
+            w => panic!("unexpected warnings {:?}", w),
+        }
+
+        // 9mm engaged is enough for M8
+        let op =
+            replace_component(&design.instances[&bolt_id], &lib.components["Bolt-M8-12"]).unwrap();
+        design.push(op).unwrap();
+        assert!(design.thread_warnings().0.is_empty());
+
+        // off the axis of the hole
+        design
+            .push(DesignOperation::MoveInstance(MoveInstance {
+                id: bolt_id,
+                new_matrix: Isometry3::from_parts(
+                    Translation3::new(0.503, 0.01, 0.0),
+                    UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f64::consts::PI),
+                ),
+                old_matrix: None,
+            }))
+            .unwrap();
+        assert_eq!(
+            design
+                .thread_warnings()
+                .0
+                .into_iter()
+                .map(|w| w.warning)
+                .collect::<Vec<_>>(),
+            vec![ScrewWarning::NotCoaxial]
+        );
+    }
+}
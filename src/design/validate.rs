@@ -0,0 +1,394 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+use std::fmt;
+
+use uuid::Uuid;
+use wasm_bindgen::JsValue;
+
+use crate::{
+    component::{check_slot_compatible, ComponentType, Incompatible},
+    instance::{Instance, InstanceConfig},
+};
+
+use super::{
+    operation::{DesignOperation, Operation},
+    DesignSpace,
+};
+
+// 无法执行的操作, 不会被记录
+#[derive(Debug, Clone, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum OperationError {
+    MissingInstance(String),
+    DuplicateInstance(String),
+    ConfigMismatch(String), // 配置类型与实例不符
+    InvalidSize(String),    // 尺寸不能小于等于 0
+    MissingJoint(String),
+    DuplicateJoint(String),
+    MissingPattern(String),
+    IncompatibleConnector(String), // 连接件不适配所连型材的槽口
+    MissingComponent(String),      // 组件未注册到设计
+}
+
+impl fmt::Display for OperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationError::MissingInstance(id) => write!(f, "instance not found: {}", id),
+            OperationError::DuplicateInstance(id) => write!(f, "instance already exists: {}", id),
+            OperationError::ConfigMismatch(id) => {
+                write!(f, "config does not fit instance: {}", id)
+            }
+            OperationError::InvalidSize(id) => write!(f, "invalid size of instance: {}", id),
+            OperationError::MissingJoint(id) => write!(f, "joint not found: {}", id),
+            OperationError::DuplicateJoint(id) => write!(f, "joint already exists: {}", id),
+            OperationError::MissingPattern(id) => write!(f, "pattern not found: {}", id),
+            OperationError::IncompatibleConnector(id) => {
+                write!(f, "connector does not fit the profile slot: {}", id)
+            }
+            OperationError::MissingComponent(label) => write!(f, "component not found: {}", label),
+        }
+    }
+}
+
+impl From<OperationError> for JsValue {
+    fn from(e: OperationError) -> Self {
+        match e.into_js() {
+            Ok(js) => js.into(),
+            Err(_) => JsValue::from_str(&e.to_string()),
+        }
+    }
+}
+
+fn grow(size: u32, d: i32) -> Option<u32> {
+    let size = size as i64 + d as i64;
+    (size > 0).then_some(size as u32)
+}
+
+/// a slot connector jointed to a profile must fit its slot, unregistered components are not checked
+fn is_slot_compatible(space: &DesignSpace, a: &Instance, b: &Instance) -> bool {
+    let component = |i: &Instance| space.components.components.get(&i.component_label);
+    let (Some(a), Some(b)) = (component(a), component(b)) else {
+        return true;
+    };
+    [(a, b), (b, a)].iter().all(|(profile, connector)| {
+        matches!(
+            check_slot_compatible(profile, connector),
+            Ok(()) | Err(Incompatible::NotExtrude | Incompatible::NotSlotConnector)
+        )
+    })
+}
+
+impl DesignOperation {
+    /// check that the operation applies to the current state of `space`
+    pub(crate) fn validate(&self, space: &DesignSpace) -> Result<(), OperationError> {
+        let instance = |id: &Uuid| {
+            space
+                .instances
+                .get(id)
+                .ok_or_else(|| OperationError::MissingInstance(id.to_string()))
+        };
+        match self {
+            DesignOperation::AddInstance(op) => {
+                if space.instances.contains_key(&op.instance.id) {
+                    return Err(OperationError::DuplicateInstance(
+                        op.instance.id.to_string(),
+                    ));
+                }
+            }
+            DesignOperation::RemoveInstance(op) => {
+                instance(&op.id)?;
+            }
+            DesignOperation::MoveInstance(op) => {
+                instance(&op.id)?;
+            }
+            DesignOperation::SetInstance(op) => {
+                instance(&op.instance.id)?;
+            }
+            DesignOperation::PostProcessInstance(op) => {
+                match (&op.config, &instance(&op.id)?.config) {
+                    (InstanceConfig::Extrude(_), InstanceConfig::Extrude(_))
+                    | (InstanceConfig::Panel(_), InstanceConfig::Panel(_))
+                    | (InstanceConfig::Normal, InstanceConfig::Normal) => {}
+                    _ => return Err(OperationError::ConfigMismatch(op.id.to_string())),
+                }
+            }
+            DesignOperation::ExtrudeAddLength(op) => match &instance(&op.id)?.config {
+                InstanceConfig::Extrude(e) => {
+                    grow(e.length, op.dlength)
+                        .ok_or_else(|| OperationError::InvalidSize(op.id.to_string()))?;
+                }
+                _ => return Err(OperationError::ConfigMismatch(op.id.to_string())),
+            },
+            DesignOperation::PanelAddSize(op) => match &instance(&op.id)?.config {
+                InstanceConfig::Panel(p) => {
+                    grow(p.x, op.dx)
+                        .and(grow(p.y, op.dy))
+                        .and(grow(p.thickness, op.dthickness))
+                        .ok_or_else(|| OperationError::InvalidSize(op.id.to_string()))?;
+                }
+                _ => return Err(OperationError::ConfigMismatch(op.id.to_string())),
+            },
+            DesignOperation::ReplaceComponent(op) => {
+                instance(&op.id)?;
+                let component = space
+                    .components
+                    .components
+                    .get(&op.component_label)
+                    .ok_or_else(|| OperationError::MissingComponent(op.component_label.clone()))?;
+                let valid = ComponentType::from_data(&component.data) == op.component_type
+                    && (!matches!(op.config, InstanceConfig::Extrude(_))
+                        || op.config.is_extrude_config_valid(component));
+                if !valid || !op.config.fits(op.component_type) {
+                    return Err(OperationError::ConfigMismatch(op.id.to_string()));
+                }
+                let replaced = Instance {
+                    component_label: op.component_label.clone(),
+                    ..instance(&op.id)?.clone()
+                };
+                for joint in space.joints.values() {
+                    let other = match (joint.a == op.id, joint.b == op.id) {
+                        (true, _) => &joint.b,
+                        (_, true) => &joint.a,
+                        _ => continue,
+                    };
+                    if !is_slot_compatible(space, &replaced, instance(other)?) {
+                        return Err(OperationError::IncompatibleConnector(joint.id.to_string()));
+                    }
+                }
+            }
+            DesignOperation::SplitExtrude(op) => {
+                if space.instances.contains_key(&op.right.id) {
+                    return Err(OperationError::DuplicateInstance(op.right.id.to_string()));
+                }
+                if !matches!(instance(&op.left.id)?.config, InstanceConfig::Extrude(_)) {
+                    return Err(OperationError::ConfigMismatch(op.left.id.to_string()));
+                }
+                for id in op.moved_joints.iter() {
+                    match space.joints.get(id) {
+                        Some(joint) if joint.a == op.left.id || joint.b == op.left.id => {}
+                        _ => return Err(OperationError::MissingJoint(id.to_string())),
+                    }
+                }
+            }
+            DesignOperation::JoinExtrude(op) => {
+                let (a, b) = (instance(&op.instance.id)?, instance(&op.other)?);
+                let component = space
+                    .components
+                    .components
+                    .get(&a.component_label)
+                    .ok_or_else(|| OperationError::MissingComponent(a.component_label.clone()))?;
+                if !op.is_join_of(a, b, component) {
+                    return Err(OperationError::ConfigMismatch(op.instance.id.to_string()));
+                }
+            }
+            DesignOperation::Pattern(op) => {
+                for id in op.feature.sources.iter() {
+                    instance(id)?;
+                }
+                if let Some(copy) = op
+                    .copies
+                    .iter()
+                    .find(|c| space.instances.contains_key(&c.id))
+                {
+                    return Err(OperationError::DuplicateInstance(copy.id.to_string()));
+                }
+            }
+            DesignOperation::EditPattern(op) => {
+                if !space.patterns.contains_key(&op.feature.id) {
+                    return Err(OperationError::MissingPattern(op.feature.id.to_string()));
+                }
+            }
+            // 特征可以引用已删除的实例, 编辑阵列时会去掉它们
+            DesignOperation::SetPattern(_) => {}
+            DesignOperation::AddJoint(op) => {
+                if space.joints.contains_key(&op.joint.id) {
+                    return Err(OperationError::DuplicateJoint(op.joint.id.to_string()));
+                }
+                if !is_slot_compatible(space, instance(&op.joint.a)?, instance(&op.joint.b)?) {
+                    return Err(OperationError::IncompatibleConnector(
+                        op.joint.id.to_string(),
+                    ));
+                }
+            }
+            DesignOperation::RemoveJoint(op) => {
+                if !space.joints.contains_key(&op.id) {
+                    return Err(OperationError::MissingJoint(op.id.to_string()));
+                }
+            }
+            // checked one by one while applied
+            DesignOperation::Batch(_) => {}
+        }
+        Ok(())
+    }
+}
+
+impl DesignSpace {
+    /// validate and operate, nothing is changed if any part of the operation fails
+    pub(crate) fn try_operate(&mut self, op: &mut DesignOperation) -> Result<(), OperationError> {
+        if let DesignOperation::Batch(batch) = op {
+            for i in 0..batch.operations.len() {
+                if let Err(e) = self.try_operate(&mut batch.operations[i]) {
+                    for op in batch.operations[..i].iter_mut().rev() {
+                        op.inverse(self);
+                    }
+                    return Err(e);
+                }
+            }
+            return Ok(());
+        }
+        op.validate(self)?;
+        op.operate(self);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        component::{test_util::slot_connector_lib, ComponentLib},
+        design::{
+            joint::Joint,
+            operation::{
+                add_extrude_instance, add_normal_instance, add_slot_connector_instance,
+                replace_component, AddJoint, Batch, ExtrudeAddLength, MoveInstance, PanelAddSize,
+                RemoveInstance,
+            },
+        },
+    };
+    use nalgebra::Isometry3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn missing_instance_test() {
+        let mut design = DesignSpace::new();
+        let id = Uuid::new_v4();
+        let result = design.push(DesignOperation::MoveInstance(MoveInstance {
+            id,
+            new_matrix: Isometry3::identity(),
+            old_matrix: None,
+        }));
+        assert_eq!(result, Err(OperationError::MissingInstance(id.to_string())));
+        assert!(design.records.is_empty());
+        assert!(design.log.entries.is_empty());
+        // nothing to undo, nothing panics
+        assert!(design.pop().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn invalid_operation_test() {
+        let lib = ComponentLib::default();
+        let mut design = DesignSpace::new();
+        let op = add_extrude_instance(
+            &mut design,
+            lib.components.get("LCF8-4040").unwrap(),
+            100000,
+        )
+        .unwrap();
+        let id = op.instance_ids()[0];
+        design.push(op).unwrap();
+
+        assert_eq!(
+            design.push(DesignOperation::ExtrudeAddLength(ExtrudeAddLength {
+                id,
+                dlength: -100000,
+                new_matrix: Isometry3::identity(),
+                old_matrix: None,
+            })),
+            Err(OperationError::InvalidSize(id.to_string()))
+        );
+        assert_eq!(
+            design.push(DesignOperation::PanelAddSize(PanelAddSize {
+                id,
+                dx: 100,
+                dy: 0,
+                dthickness: 0,
+                new_matrix: Isometry3::identity(),
+                old_matrix: None,
+            })),
+            Err(OperationError::ConfigMismatch(id.to_string()))
+        );
+
+        // a failing batch leaves no partial edit behind
+        let result = design.push(DesignOperation::Batch(Batch {
+            operations: vec![
+                DesignOperation::MoveInstance(MoveInstance {
+                    id,
+                    new_matrix: Isometry3::translation(1.0, 0.0, 0.0),
+                    old_matrix: None,
+                }),
+                DesignOperation::RemoveInstance(RemoveInstance {
+                    id,
+                    removed_instance: None,
+                    removed_joints: Vec::new(),
+                }),
+                DesignOperation::MoveInstance(MoveInstance {
+                    id,
+                    new_matrix: Isometry3::identity(),
+                    old_matrix: None,
+                }),
+            ],
+        }));
+        assert_eq!(result, Err(OperationError::MissingInstance(id.to_string())));
+        assert_eq!(design.instances[&id].matrix, Isometry3::identity());
+        assert_eq!(design.records.len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn incompatible_connector_test() {
+        let lib = slot_connector_lib();
+        let mut design = DesignSpace::new();
+        for label in ["LCF8-4040", "Bracket-4040", "SlotBracket-20"] {
+            design.add_component(&lib.components[label]);
+        }
+        let op = add_extrude_instance(&mut design, &lib.components["LCF8-4040"], 100000).unwrap();
+        let profile = design_instance(&op);
+        design.push(op).unwrap();
+
+        // a connector that does not fit the slot is refused by the wasm api
+        assert!(add_slot_connector_instance(
+            &mut design,
+            &profile,
+            &lib.components["SlotBracket-20"]
+        )
+        .is_err());
+        // and by validate when jointed by hand
+        let op = add_normal_instance(&mut design, &lib.components["SlotBracket-20"]).unwrap();
+        let slot_bracket = design_instance(&op);
+        design.push(op).unwrap();
+        let joint = Joint::new(design.ids.next_id(), profile.id, slot_bracket.id);
+        assert_eq!(
+            design.push(DesignOperation::AddJoint(AddJoint {
+                joint: joint.clone()
+            })),
+            Err(OperationError::IncompatibleConnector(joint.id.to_string()))
+        );
+
+        let op =
+            add_slot_connector_instance(&mut design, &profile, &lib.components["Bracket-4040"])
+                .unwrap();
+        let bracket = op.instance_ids()[0];
+        design.push(op).unwrap();
+        assert_eq!(design.joints.len(), 1);
+        // the jointed bracket cannot be swapped for a connector of another series
+        let op = replace_component(
+            &design.instances[&bracket],
+            &lib.components["SlotBracket-20"],
+        )
+        .unwrap();
+        assert!(matches!(
+            design.push(op),
+            Err(OperationError::IncompatibleConnector(_))
+        ));
+    }
+
+    fn design_instance(op: &DesignOperation) -> Instance {
+        match op {
+            DesignOperation::AddInstance(op) => op.instance.clone(),
+            _ => unreachable!(),
+        }
+    }
+}
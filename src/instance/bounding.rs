@@ -0,0 +1,282 @@
+use nalgebra::{Isometry3, Point3, Vector3};
+
+use crate::{
+    component::{Component, ComponentData},
+    unit::LENGTH_UNIT,
+};
+
+use super::{Instance, InstanceConfig};
+
+// 小于该值的重叠视为贴合 m
+pub(crate) const CONTACT_TOLERANCE: f32 = 0.00001;
+
+// 轴对齐包围盒 m
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Aabb {
+    pub(crate) min: Point3<f32>,
+    pub(crate) max: Point3<f32>,
+}
+
+impl Aabb {
+    pub(crate) fn new(a: Point3<f32>, b: Point3<f32>) -> Self {
+        Aabb {
+            min: a.inf(&b),
+            max: a.sup(&b),
+        }
+    }
+
+    pub(crate) fn merge(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub(crate) fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2. * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub(crate) fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+
+    /// distance along `dir` to where the ray enters the box, None if missed
+    pub(crate) fn ray_distance(&self, origin: &Point3<f32>, dir: &Vector3<f32>) -> Option<f32> {
+        self.ray_interval(origin, dir).map(|(enter, _)| enter)
+    }
+
+    /// distances along `dir` to where the ray enters and leaves the box, None if missed
+    pub(crate) fn ray_interval(
+        &self,
+        origin: &Point3<f32>,
+        dir: &Vector3<f32>,
+    ) -> Option<(f32, f32)> {
+        let mut t_min = 0f32;
+        let mut t_max = f32::MAX;
+        for i in 0..3 {
+            if dir[i].abs() < f32::EPSILON {
+                if origin[i] < self.min[i] || origin[i] > self.max[i] {
+                    return None;
+                }
+            } else {
+                let t1 = (self.min[i] - origin[i]) / dir[i];
+                let t2 = (self.max[i] - origin[i]) / dir[i];
+                t_min = t_min.max(t1.min(t2));
+                t_max = t_max.min(t1.max(t2));
+                if t_min > t_max {
+                    return None;
+                }
+            }
+        }
+        Some((t_min, t_max))
+    }
+
+    /// whether the box is completely on the negative side of `normal · p + constant = 0`
+    pub(crate) fn outside_plane(&self, normal: &Vector3<f32>, constant: f32) -> bool {
+        // the corner furthest along the normal
+        let p = Vector3::new(
+            if normal.x >= 0. {
+                self.max.x
+            } else {
+                self.min.x
+            },
+            if normal.y >= 0. {
+                self.max.y
+            } else {
+                self.min.y
+            },
+            if normal.z >= 0. {
+                self.max.z
+            } else {
+                self.min.z
+            },
+        );
+        normal.dot(&p) + constant < 0.
+    }
+}
+
+// 有向包围盒, 中心与朝向取实例矩阵
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Obb {
+    pub(crate) matrix: Isometry3<f32>,
+    pub(crate) half_extents: Vector3<f32>, // m
+}
+
+impl Obb {
+    pub(crate) fn center(&self) -> Point3<f32> {
+        self.matrix.translation.vector.into()
+    }
+
+    pub(crate) fn axes(&self) -> [Vector3<f32>; 3] {
+        let r = self.matrix.rotation;
+        [r * Vector3::x(), r * Vector3::y(), r * Vector3::z()]
+    }
+
+    /// half size of the box projected on `axis`
+    pub(crate) fn radius(&self, axis: &Vector3<f32>) -> f32 {
+        self.axes()
+            .iter()
+            .zip(self.half_extents.iter())
+            .map(|(u, e)| e * u.dot(axis).abs())
+            .sum()
+    }
+
+    pub(crate) fn aabb(&self) -> Aabb {
+        let extent: Vector3<f32> = self
+            .axes()
+            .iter()
+            .zip(self.half_extents.iter())
+            .map(|(u, e)| u.abs() * *e)
+            .sum();
+        let center = self.center();
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+
+    /// distance along `dir` to where the ray enters the box, None if missed
+    pub(crate) fn ray_distance(&self, origin: &Point3<f32>, dir: &Vector3<f32>) -> Option<f32> {
+        self.ray_interval(origin, dir).map(|(enter, _)| enter)
+    }
+
+    /// distances along `dir` to where the ray enters and leaves the box, None if missed
+    pub(crate) fn ray_interval(
+        &self,
+        origin: &Point3<f32>,
+        dir: &Vector3<f32>,
+    ) -> Option<(f32, f32)> {
+        let local = Aabb {
+            min: (-self.half_extents).into(),
+            max: self.half_extents.into(),
+        };
+        local.ray_interval(
+            &self.matrix.inverse_transform_point(origin),
+            &self.matrix.inverse_transform_vector(dir),
+        )
+    }
+
+    /// penetration depth of two boxes by the separating axis test, None if they only touch or do not overlap
+    pub(crate) fn penetration(&self, other: &Obb) -> Option<f32> {
+        let a = self.axes();
+        let b = other.axes();
+        let mut axes: Vec<Vector3<f32>> = a.iter().chain(b.iter()).copied().collect();
+        for u in a.iter() {
+            for v in b.iter() {
+                let n = u.cross(v);
+                // parallel edges are covered by the face axes
+                if n.norm_squared() > 1e-10 {
+                    axes.push(n.normalize());
+                }
+            }
+        }
+
+        let d = other.center() - self.center();
+        let mut depth = f32::MAX;
+        for axis in axes.iter() {
+            let overlap = self.radius(axis) + other.radius(axis) - d.dot(axis).abs();
+            if overlap <= CONTACT_TOLERANCE {
+                return None;
+            }
+            depth = depth.min(overlap);
+        }
+        Some(depth)
+    }
+}
+
+impl Instance {
+    /// bounding box of the instance, None if the component has no known size
+    pub(crate) fn bounding_box(&self, component: &Component) -> Option<Obb> {
+        let half_extents = match (&self.config, &component.data) {
+            (InstanceConfig::Extrude(e), ComponentData::Extrude(data)) => {
+                let (y, z) = data.section_size();
+                Vector3::new(e.length as f32 / LENGTH_UNIT, y, z) / 2.
+            }
+            (InstanceConfig::Panel(p), ComponentData::Panel(_)) => {
+                Vector3::new(
+                    p.x as f32 / LENGTH_UNIT,
+                    p.thickness as f32 / LENGTH_UNIT,
+                    p.y as f32 / LENGTH_UNIT,
+                ) / 2.
+            }
+            _ => return None,
+        };
+        Some(Obb {
+            matrix: self.isometry(),
+            half_extents,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::component::ComponentLib;
+    use nalgebra::{Translation3, UnitQuaternion};
+    use uuid::Uuid;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn bounding_box_test() {
+        let lib = ComponentLib::default();
+        let component = lib.components.get("LCF8-4080").unwrap();
+        let instance = Instance::default_extrude(Uuid::new_v4(), component, 100000).unwrap();
+        let obb = instance.bounding_box(component).unwrap();
+        assert_eq!(obb.half_extents, Vector3::new(0.5, 0.04, 0.02));
+
+        let component = lib.components.get("Accessory-test").unwrap();
+        let instance = Instance::default_component(Uuid::new_v4(), component);
+        assert!(instance.bounding_box(component).is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn penetration_test() {
+        let a = Obb {
+            matrix: Isometry3::identity(),
+            half_extents: Vector3::new(0.5, 0.02, 0.02),
+        };
+        // crossing at the middle, 10mm overlap in z
+        let b = Obb {
+            matrix: Isometry3::from_parts(
+                Translation3::new(0.0, 0.0, 0.03),
+                UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f32::consts::FRAC_PI_2),
+            ),
+            half_extents: Vector3::new(0.5, 0.02, 0.02),
+        };
+        assert!((a.penetration(&b).unwrap() - 0.01).abs() < 1e-5);
+
+        // touching faces do not overlap
+        let c = Obb {
+            matrix: Isometry3::translation(0.0, 0.04, 0.0),
+            half_extents: Vector3::new(0.5, 0.02, 0.02),
+        };
+        assert!(a.penetration(&c).is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn ray_distance_test() {
+        let obb = Obb {
+            matrix: Isometry3::from_parts(
+                Translation3::new(0.0, 1.0, 0.0),
+                UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f32::consts::FRAC_PI_2),
+            ),
+            half_extents: Vector3::new(0.5, 0.02, 0.02),
+        };
+        let aabb = obb.aabb();
+        assert!((aabb.min - Point3::new(-0.02, 0.5, -0.02)).norm() < 1e-5);
+        assert!((aabb.max - Point3::new(0.02, 1.5, 0.02)).norm() < 1e-5);
+
+        let t = obb
+            .ray_distance(&Point3::new(-1.0, 1.2, 0.0), &Vector3::x())
+            .unwrap();
+        assert!((t - 0.98).abs() < 1e-5);
+        assert!(obb
+            .ray_distance(&Point3::new(-1.0, 1.6, 0.0), &Vector3::x())
+            .is_none());
+        assert!(obb
+            .ray_distance(&Point3::new(1.0, 1.2, 0.0), &Vector3::x())
+            .is_none());
+    }
+}
@@ -1,4 +1,6 @@
 #![allow(non_snake_case, clippy::empty_docs)]
+mod bounding;
+
 use nalgebra::Isometry3;
 use serde::{Deserialize, Serialize};
 use tsify::Tsify;
@@ -7,16 +9,20 @@ use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::{
     component::{Component, ComponentData, ComponentType, ExtrudeData},
+    unit::{position_in, snap_position, LengthUnit},
     Quaternion, Translation,
 };
 
+pub(crate) use crate::unit::{LENGTH_UNIT, POSITION_LENGTH_UNIT};
+pub(crate) use bounding::{Aabb, Obb};
+
 #[wasm_bindgen]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Instance {
     pub(crate) id: Uuid,
     pub(crate) component_label: String,
     pub(crate) component_type: ComponentType,
-    pub(crate) matrix: Isometry3<f32>,
+    pub(crate) matrix: Isometry3<f64>, // 位移 m, f64 保证反复编辑不漂移
     pub(crate) config: InstanceConfig,
 }
 
@@ -34,20 +40,21 @@ impl Instance {
         self.component_type
     }
 
+    /// translation in m, rounded to f32 for rendering
     pub fn trans(&self) -> Translation {
         Translation {
-            x: self.matrix.translation.x,
-            y: self.matrix.translation.y,
-            z: self.matrix.translation.z,
+            x: self.matrix.translation.x as f32,
+            y: self.matrix.translation.y as f32,
+            z: self.matrix.translation.z as f32,
         }
     }
 
     pub fn quat(&self) -> Quaternion {
         Quaternion {
-            i: self.matrix.rotation.i,
-            j: self.matrix.rotation.j,
-            k: self.matrix.rotation.k,
-            w: self.matrix.rotation.w,
+            i: self.matrix.rotation.i as f32,
+            j: self.matrix.rotation.j as f32,
+            k: self.matrix.rotation.k as f32,
+            w: self.matrix.rotation.w as f32,
         }
     }
 
@@ -66,17 +73,38 @@ impl Instance {
     pub fn is_config_equal(&self, other: &Instance) -> bool {
         self.config == other.config
     }
+
+    /// translation [x, y, z] in `unit`, exact for BOMs and drawings
+    pub fn position(&self, unit: LengthUnit) -> Vec<f64> {
+        self.matrix
+            .translation
+            .vector
+            .iter()
+            .map(|v| position_in(*v, unit))
+            .collect()
+    }
 }
 
 impl Instance {
-    pub(crate) fn default_component(component: &Component) -> Self {
+    /// move the translation onto the position grid
+    pub(crate) fn snap_position(&mut self) {
+        let t = &mut self.matrix.translation.vector;
+        *t = t.map(snap_position);
+    }
+
+    /// the placement in f32, for geometry checks and rendering only
+    pub(crate) fn isometry(&self) -> Isometry3<f32> {
+        self.matrix.cast()
+    }
+
+    pub(crate) fn default_component(id: Uuid, component: &Component) -> Self {
         let config = match component.data {
             ComponentData::Extrude(_) => InstanceConfig::default_extrude(100000),
             ComponentData::Panel(_) => InstanceConfig::panel(100000, 100000, 2000),
             _ => InstanceConfig::Normal,
         };
         Instance {
-            id: Uuid::new_v4(),
+            id,
             component_label: component.label.clone(),
             component_type: ComponentType::from_data(&component.data),
             matrix: Isometry3::identity(),
@@ -84,13 +112,17 @@ impl Instance {
         }
     }
 
-    pub(crate) fn default_extrude(component: &Component, length: u32) -> Option<Instance> {
+    pub(crate) fn default_extrude(
+        id: Uuid,
+        component: &Component,
+        length: u32,
+    ) -> Option<Instance> {
         match &component.data {
             ComponentData::Extrude(_) => {
                 let config = InstanceConfig::default_extrude(length);
                 if config.is_extrude_config_valid(component) {
                     Some(Instance {
-                        id: Uuid::new_v4(),
+                        id,
                         component_label: component.label.clone(),
                         component_type: ComponentType::from_data(&component.data),
                         matrix: Isometry3::identity(),
@@ -105,6 +137,7 @@ impl Instance {
     }
 
     pub(crate) fn default_panel(
+        id: Uuid,
         component: &Component,
         x: u32,
         y: u32,
@@ -112,7 +145,7 @@ impl Instance {
     ) -> Option<Instance> {
         match &component.data {
             ComponentData::Panel(_) => Some(Instance {
-                id: Uuid::new_v4(),
+                id,
                 component_label: component.label.clone(),
                 component_type: ComponentType::from_data(&component.data),
                 matrix: Isometry3::identity(),
@@ -153,6 +186,46 @@ impl InstanceConfig {
         })
     }
 
+    /// the config this instance keeps when its component is replaced by `component`,
+    /// post processes the new component does not support are dropped
+    pub(crate) fn carry_over(&self, component: &Component) -> Self {
+        match (self, &component.data) {
+            (
+                InstanceConfig::Extrude(c),
+                ComponentData::Extrude(ExtrudeData { post_process, .. }),
+            ) => {
+                let mut c = c.clone();
+                if !post_process.bevel_cut {
+                    c.bevel_cut = None;
+                }
+                if !post_process.wrench_hole {
+                    c.wrench_hole_left = None;
+                    c.wrench_hole_right = None;
+                }
+                if !post_process.counterbore {
+                    c.counterbore_left = 0;
+                    c.counterbore_right = 0;
+                }
+                InstanceConfig::Extrude(c)
+            }
+            (InstanceConfig::Panel(p), ComponentData::Panel(_)) => InstanceConfig::Panel(p.clone()),
+            _ => Instance::default_component(Uuid::nil(), component).config,
+        }
+    }
+
+    /// whether the config kind is the one used by instances of `component_type`
+    pub(crate) fn fits(&self, component_type: ComponentType) -> bool {
+        matches!(
+            (self, component_type),
+            (InstanceConfig::Extrude(_), ComponentType::Extrude)
+                | (InstanceConfig::Panel(_), ComponentType::Panel)
+        ) || (*self == InstanceConfig::Normal
+            && !matches!(
+                component_type,
+                ComponentType::Extrude | ComponentType::Panel
+            ))
+    }
+
     pub fn is_extrude_config_valid(&self, component: &Component) -> bool {
         match (self, &component.data) {
             (
@@ -197,6 +270,16 @@ pub struct ExtrudeConfig {
     pub length: u32,                           // 长度 精度：0.01mm
 }
 
+impl ExtrudeConfig {
+    /// swap the machining of the two ends, for an extrusion turned end to end. The bevel cut
+    /// is the same at both ends and its direction is across the section, so it stays.
+    pub(crate) fn swap_ends(&mut self) {
+        std::mem::swap(&mut self.drill_left, &mut self.drill_right);
+        std::mem::swap(&mut self.wrench_hole_left, &mut self.wrench_hole_right);
+        std::mem::swap(&mut self.counterbore_left, &mut self.counterbore_right);
+    }
+}
+
 #[derive(Debug, Clone, Copy, Tsify, Serialize, Deserialize, PartialEq, Eq)]
 pub struct WrenchHole {
     pub number: WrenchHoleNumber,
@@ -245,7 +328,7 @@ mod test {
     fn instance_test() {
         let lib = ComponentLib::default();
         lib.components.iter().for_each(|(_label, c)| {
-            let _i = Instance::default_component(c);
+            let _i = Instance::default_component(Uuid::new_v4(), c);
         });
     }
 }
@@ -5,6 +5,7 @@ mod assembly_node;
 mod component;
 mod design;
 mod instance;
+mod unit;
 mod utils;
 
 
@@ -23,7 +24,7 @@ mod trans_rot_types {
     use serde::{Deserialize, Serialize};
     use tsify::Tsify;
 
-    #[derive(Debug, Tsify, Serialize, Deserialize)]
+    #[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
     #[tsify(into_wasm_abi, from_wasm_abi)]
     pub struct Translation {
         pub x: f32,
@@ -41,7 +42,7 @@ mod trans_rot_types {
         }
     }
 
-    #[derive(Debug, Tsify, Serialize, Deserialize)]
+    #[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
     #[tsify(into_wasm_abi, from_wasm_abi)]
     pub struct Quaternion {
         pub i: f32,
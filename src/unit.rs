@@ -0,0 +1,142 @@
+#![allow(non_snake_case, clippy::empty_docs)]
+use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{Quaternion, Translation};
+
+// 长度单位, 组件与实例配置中的长度都是整数 0.01mm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum LengthUnit {
+    Hundredth, // 0.01mm
+    Millimeter,
+    Meter,
+    Inch,
+}
+
+impl LengthUnit {
+    /// how many 0.01mm one of this unit is
+    pub(crate) const fn hundredths(self) -> f64 {
+        match self {
+            LengthUnit::Hundredth => 1.,
+            LengthUnit::Millimeter => 100.,
+            LengthUnit::Meter => 100000.,
+            LengthUnit::Inch => 2540.,
+        }
+    }
+}
+
+// 0.01mm -> m, 用于 f32 的几何计算
+pub(crate) const LENGTH_UNIT: f32 = LengthUnit::Meter.hundredths() as f32;
+
+// 0.01mm -> m, 用于实例位置
+pub(crate) const POSITION_LENGTH_UNIT: f64 = LengthUnit::Meter.hundredths();
+
+// 实例位置的网格 1/m, 为长度精度的一半, 任意长度型材的中心都落在网格上
+const POSITION_UNIT: f64 = 2. * POSITION_LENGTH_UNIT;
+
+/// the nearest point of the position grid to `v` m. Positions are kept in f64, where grid
+/// points are exact out to about ±2×10^10 m, so a snapped position never moves again
+pub(crate) fn snap_position(v: f64) -> f64 {
+    (v * POSITION_UNIT).round() / POSITION_UNIT
+}
+
+/// `v` m on the position grid expressed in `unit`
+pub(crate) fn position_in(v: f64, unit: LengthUnit) -> f64 {
+    convert_length(
+        (v * POSITION_UNIT).round() / 2.,
+        LengthUnit::Hundredth,
+        unit,
+    )
+}
+
+/// a placement given by the front end in f32, its translation on the position grid
+pub(crate) fn placement(tra: &Translation, quat: &Quaternion) -> Isometry3<f64> {
+    Isometry3::from_parts(
+        Translation3::new(
+            snap_position(tra.x as f64),
+            snap_position(tra.y as f64),
+            snap_position(tra.z as f64),
+        ),
+        UnitQuaternion::new_normalize(nalgebra::Quaternion::new(
+            quat.w as f64,
+            quat.i as f64,
+            quat.j as f64,
+            quat.k as f64,
+        )),
+    )
+}
+
+/// how many 0.01mm one meter is. Config lengths are in 0.01mm and instance positions in m,
+/// convert between them with this rather than a literal
+#[wasm_bindgen]
+pub fn hundredths_per_meter() -> f64 {
+    POSITION_LENGTH_UNIT
+}
+
+/// `value` in `from` expressed in `to`
+#[wasm_bindgen]
+pub fn convert_length(value: f64, from: LengthUnit, to: LengthUnit) -> f64 {
+    value * from.hundredths() / to.hundredths()
+}
+
+/// `value` in `unit` as a length of a component or an instance config, rounded to 0.01mm
+#[wasm_bindgen]
+pub fn to_length(value: f64, unit: LengthUnit) -> Result<u32, String> {
+    let length = (value * unit.hundredths()).round();
+    if !(0. ..=u32::MAX as f64).contains(&length) {
+        return Err(format!("invalid length: {}", value));
+    }
+    Ok(length as u32)
+}
+
+/// a length in 0.01mm expressed in `unit`
+#[wasm_bindgen]
+pub fn from_length(length: u32, unit: LengthUnit) -> f64 {
+    convert_length(length as f64, LengthUnit::Hundredth, unit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn length_unit_test() {
+        assert_eq!(to_length(1500.3, LengthUnit::Millimeter), Ok(150030));
+        assert_eq!(to_length(1.5, LengthUnit::Meter), Ok(150000));
+        assert_eq!(to_length(2., LengthUnit::Inch), Ok(5080));
+        assert!(to_length(-1., LengthUnit::Millimeter).is_err());
+        assert_eq!(from_length(150030, LengthUnit::Millimeter), 1500.3);
+        assert_eq!(from_length(5080, LengthUnit::Inch), 2.);
+        assert_eq!(
+            convert_length(25.4, LengthUnit::Millimeter, LengthUnit::Inch),
+            1.
+        );
+
+        // a drifted position snaps to the grid and stays there
+        let v = snap_position(0.1000004);
+        assert_eq!(v, 0.1);
+        assert_eq!(snap_position(0.123455), 0.123455);
+        // a thousand 0.3mm nudges end exactly 300mm further
+        let mut p = v;
+        for _ in 0..1000 {
+            p = snap_position(p + 0.0003);
+        }
+        assert_eq!(p, 0.4);
+        assert_eq!(position_in(p, LengthUnit::Millimeter), 400.);
+        assert_eq!(position_in(0.123455, LengthUnit::Hundredth), 12345.5);
+        // exact far beyond the ±32 m of f32
+        let far = 12345.678905;
+        assert_eq!(snap_position(far), far);
+        assert_eq!(snap_position(-far), -far);
+        assert_eq!(position_in(far, LengthUnit::Hundredth), 1234567890.5);
+        assert_eq!(
+            from_length(150000, LengthUnit::Meter) * hundredths_per_meter(),
+            150000.
+        );
+    }
+}